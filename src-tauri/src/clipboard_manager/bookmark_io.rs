@@ -0,0 +1,632 @@
+//! Import and export of bookmarks in other formats. Every format is read into (and
+//! written from) `PortableBookmark`s, where folders are paths of names, so folder
+//! ids never leak from one install to another.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::history::new_id;
+use crate::commands::{Bookmark, BookmarkFolder};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BookmarkFormat {
+    /// clipbored's own format: `{ "bookmarks": [...], "folders": [...] }`, or a bookmarks.json copy
+    Json,
+    /// Columns: content, title, description, tags (`;` separated), folder (`/` separated)
+    Csv,
+    /// One text file per bookmark, subdirectories are folders
+    Directory,
+    /// An Espanso match file (`matches:` with `trigger` and `replace`)
+    Espanso,
+}
+
+/// What happens to an imported bookmark whose content is already bookmarked.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicatePolicy {
+    Skip,
+    /// Overwrite the existing bookmark's details, keeping its id and position
+    Replace,
+    KeepBoth,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub added: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+    pub folders_created: usize,
+    /// Entries of the source that could not be turned into bookmarks
+    pub unsupported: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PortableBookmark {
+    pub content: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    /// Folder names from the top level down; empty for the top level
+    pub folder: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonExport {
+    bookmarks: Vec<Bookmark>,
+    #[serde(default)]
+    folders: Vec<BookmarkFolder>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    content: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    folder: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EspansoFile {
+    matches: Vec<EspansoMatch>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EspansoMatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trigger: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    triggers: Vec<String>,
+    /// Matches without a plain replacement (forms, images, ...) can't be imported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    replace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+}
+
+/// Bookmarks with their folder paths, in list order.
+pub fn to_portable(bookmarks: &[Bookmark], folders: &[BookmarkFolder]) -> Vec<PortableBookmark> {
+    bookmarks
+        .iter()
+        .map(|bookmark| PortableBookmark {
+            content: bookmark.content.clone(),
+            title: bookmark.title.clone(),
+            description: bookmark.description.clone(),
+            tags: bookmark.tags.clone(),
+            folder: folder_path(bookmark.folder_id.as_deref(), folders),
+        })
+        .collect()
+}
+
+fn folder_path(folder_id: Option<&str>, folders: &[BookmarkFolder]) -> Vec<String> {
+    let mut path = Vec::new();
+    let mut current = folder_id;
+
+    // The length check stops on a (corrupted) folder cycle
+    while let Some(folder) = current.and_then(|id| folders.iter().find(|f| f.id == id)) {
+        if path.len() > folders.len() {
+            break;
+        }
+        path.insert(0, folder.name.clone());
+        current = folder.parent_id.as_deref();
+    }
+
+    path
+}
+
+/// Reads bookmarks from `path`. Entries that can't be imported are listed in `unsupported`.
+pub fn read(
+    path: &Path,
+    format: BookmarkFormat,
+    unsupported: &mut Vec<String>,
+) -> Result<Vec<PortableBookmark>, Box<dyn Error>> {
+    match format {
+        BookmarkFormat::Json => {
+            let contents = fs::read_to_string(path)?;
+            let export = serde_json::from_str::<JsonExport>(&contents).or_else(|_| {
+                serde_json::from_str::<Vec<Bookmark>>(&contents).map(|bookmarks| JsonExport {
+                    bookmarks,
+                    folders: vec![],
+                })
+            })?;
+            Ok(to_portable(&export.bookmarks, &export.folders))
+        }
+        BookmarkFormat::Csv => {
+            let mut reader = csv::Reader::from_path(path)?;
+            let mut bookmarks = Vec::new();
+            for row in reader.deserialize::<CsvRow>() {
+                let row = row?;
+                bookmarks.push(PortableBookmark {
+                    content: row.content,
+                    title: non_empty(row.title),
+                    description: non_empty(row.description),
+                    tags: split_list(&row.tags, ';'),
+                    folder: split_list(&row.folder, '/'),
+                });
+            }
+            Ok(bookmarks)
+        }
+        BookmarkFormat::Directory => {
+            let mut bookmarks = Vec::new();
+            read_directory(path, &mut vec![], &mut bookmarks, unsupported)?;
+            Ok(bookmarks)
+        }
+        BookmarkFormat::Espanso => {
+            let file: EspansoFile = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+            let mut bookmarks = Vec::new();
+            for espanso_match in file.matches {
+                let trigger = espanso_match
+                    .trigger
+                    .or_else(|| espanso_match.triggers.first().cloned());
+                match espanso_match.replace {
+                    Some(replace) => bookmarks.push(PortableBookmark {
+                        content: replace,
+                        title: espanso_match.label.or(trigger),
+                        ..Default::default()
+                    }),
+                    None => unsupported.push(trigger.unwrap_or_else(|| "(match without trigger)".into())),
+                }
+            }
+            Ok(bookmarks)
+        }
+    }
+}
+
+fn read_directory(
+    dir: &Path,
+    folder: &mut Vec<String>,
+    bookmarks: &mut Vec<PortableBookmark>,
+    unsupported: &mut Vec<String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        if path.is_dir() {
+            folder.push(path.file_name().unwrap_or_default().to_string_lossy().to_string());
+            read_directory(&path, folder, bookmarks, unsupported)?;
+            folder.pop();
+        } else {
+            match fs::read_to_string(&path) {
+                Ok(content) => bookmarks.push(PortableBookmark {
+                    content,
+                    title: Some(name),
+                    folder: folder.clone(),
+                    ..Default::default()
+                }),
+                // Binary files
+                Err(_) => unsupported.push(path.display().to_string()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write(
+    path: &Path,
+    format: BookmarkFormat,
+    bookmarks: &[Bookmark],
+    folders: &[BookmarkFolder],
+) -> Result<(), Box<dyn Error>> {
+    match format {
+        BookmarkFormat::Json => {
+            let export = JsonExport {
+                bookmarks: bookmarks.to_vec(),
+                folders: folders.to_vec(),
+            };
+            fs::write(path, serde_json::to_string_pretty(&export)?)?;
+        }
+        BookmarkFormat::Csv => {
+            let mut writer = csv::Writer::from_path(path)?;
+            for bookmark in to_portable(bookmarks, folders) {
+                writer.serialize(CsvRow {
+                    content: bookmark.content,
+                    title: bookmark.title.unwrap_or_default(),
+                    description: bookmark.description.unwrap_or_default(),
+                    tags: bookmark.tags.join(";"),
+                    folder: bookmark.folder.join("/"),
+                })?;
+            }
+            writer.flush()?;
+        }
+        BookmarkFormat::Directory => {
+            for bookmark in to_portable(bookmarks, folders) {
+                let dir = bookmark
+                    .folder
+                    .iter()
+                    .fold(path.to_path_buf(), |dir, name| dir.join(file_name(name)));
+                fs::create_dir_all(&dir)?;
+
+                let name = file_name(bookmark.title.as_deref().unwrap_or_else(|| first_line(&bookmark.content)));
+                let mut file = dir.join(format!("{}.txt", name));
+                let mut copy = 2;
+                while file.exists() {
+                    file = dir.join(format!("{} ({}).txt", name, copy));
+                    copy += 1;
+                }
+                fs::write(file, &bookmark.content)?;
+            }
+        }
+        BookmarkFormat::Espanso => {
+            let matches = to_portable(bookmarks, folders)
+                .into_iter()
+                .map(|bookmark| EspansoMatch {
+                    trigger: Some(format!(
+                        ":{}",
+                        file_name(bookmark.title.as_deref().unwrap_or_else(|| first_line(&bookmark.content)))
+                            .to_lowercase()
+                            .replace(' ', "-")
+                    )),
+                    triggers: vec![],
+                    replace: Some(bookmark.content),
+                    label: bookmark.title,
+                })
+                .collect();
+            fs::write(path, serde_yaml::to_string(&EspansoFile { matches })?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds `imported` to `bookmarks`, creating the folders they need. Duplicates are
+/// bookmarks with the same content.
+pub fn merge(
+    bookmarks: &mut Vec<Bookmark>,
+    folders: &mut Vec<BookmarkFolder>,
+    imported: Vec<PortableBookmark>,
+    duplicates: DuplicatePolicy,
+    report: &mut ImportReport,
+) {
+    for portable in imported {
+        let existing = bookmarks.iter().position(|b| b.content == portable.content);
+        if existing.is_some() && duplicates == DuplicatePolicy::Skip {
+            report.skipped += 1;
+            continue;
+        }
+
+        let folder_id = folder_id_for_path(folders, &portable.folder, report);
+        let bookmark = Bookmark {
+            title: portable.title,
+            description: portable.description,
+            tags: portable.tags,
+            folder_id,
+            ..Bookmark::new(portable.content)
+        };
+
+        match (existing, duplicates) {
+            (Some(index), DuplicatePolicy::Replace) => {
+                // Hotkeys and abbreviations belong to this install, imported bookmarks have none
+                let id = bookmarks[index].id.clone();
+                let hotkey = bookmarks[index].hotkey.take();
+                let abbreviation = bookmarks[index].abbreviation.take();
+                bookmarks[index] = Bookmark { id, hotkey, abbreviation, ..bookmark };
+                report.replaced += 1;
+            }
+            _ => {
+                bookmarks.push(bookmark);
+                report.added += 1;
+            }
+        }
+    }
+}
+
+/// Id of the folder at `path`, creating the missing ones along the way.
+fn folder_id_for_path(
+    folders: &mut Vec<BookmarkFolder>,
+    path: &[String],
+    report: &mut ImportReport,
+) -> Option<String> {
+    let mut parent_id: Option<String> = None;
+
+    for name in path {
+        let existing = folders
+            .iter()
+            .find(|f| &f.name == name && f.parent_id == parent_id)
+            .map(|f| f.id.clone());
+        parent_id = Some(match existing {
+            Some(id) => id,
+            None => {
+                let id = new_id();
+                folders.push(BookmarkFolder {
+                    id: id.clone(),
+                    name: name.clone(),
+                    parent_id: parent_id.clone(),
+                    path: None,
+                });
+                report.folders_created += 1;
+                id
+            }
+        });
+    }
+
+    parent_id
+}
+
+fn non_empty(text: String) -> Option<String> {
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+fn split_list(text: &str, separator: char) -> Vec<String> {
+    text.split(separator)
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+fn first_line(content: &str) -> &str {
+    let line = content.lines().next().unwrap_or("").trim();
+    match line.char_indices().nth(40) {
+        Some((end, _)) => &line[..end],
+        None => line,
+    }
+}
+
+/// Makes a title usable as a file or directory name.
+fn file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() || " -_.()".contains(c) { c } else { '_' })
+        .collect();
+    let name = name.trim().trim_start_matches('.').to_string();
+
+    if name.is_empty() { "bookmark".into() } else { name }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("clipbored-test-{}", new_id()));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn folder(id: &str, name: &str, parent_id: Option<&str>) -> BookmarkFolder {
+        BookmarkFolder {
+            id: id.into(),
+            name: name.into(),
+            parent_id: parent_id.map(String::from),
+            path: None,
+        }
+    }
+
+    /// A titled bookmark in Work/Mail and an untitled one at the top level.
+    fn library() -> (Vec<Bookmark>, Vec<BookmarkFolder>) {
+        let folders = vec![folder("work", "Work", None), folder("mail", "Mail", Some("work"))];
+        let bookmarks = vec![
+            Bookmark {
+                title: Some("Greeting".into()),
+                description: Some("Opens every mail".into()),
+                tags: vec!["mail".into(), "polite".into()],
+                folder_id: Some("mail".into()),
+                ..Bookmark::new("Hello,\nhow are you?".into())
+            },
+            Bookmark::new("SELECT * FROM \"users\";".into()),
+        ];
+
+        (bookmarks, folders)
+    }
+
+    fn round_trip(format: BookmarkFormat, file: &str) -> (Vec<PortableBookmark>, Vec<String>) {
+        let dir = TempDir::new();
+        let path = dir.0.join(file);
+        let (bookmarks, folders) = library();
+        let mut unsupported = vec![];
+
+        write(&path, format, &bookmarks, &folders).unwrap();
+        let read = read(&path, format, &mut unsupported).unwrap();
+
+        (read, unsupported)
+    }
+
+    #[test]
+    fn resolves_folder_paths() {
+        let (bookmarks, folders) = library();
+        let portable = to_portable(&bookmarks, &folders);
+
+        assert_eq!(portable[0].folder, vec!["Work", "Mail"]);
+        assert!(portable[1].folder.is_empty());
+    }
+
+    #[test]
+    fn folder_cycles_stop() {
+        let folders = vec![folder("a", "A", Some("b")), folder("b", "B", Some("a"))];
+
+        assert!(folder_path(Some("a"), &folders).len() <= folders.len() + 1);
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let (bookmarks, folders) = library();
+
+        assert_eq!(round_trip(BookmarkFormat::Json, "export.json").0, to_portable(&bookmarks, &folders));
+    }
+
+    #[test]
+    fn json_reads_a_bookmarks_file_copy() {
+        let dir = TempDir::new();
+        let path = dir.0.join("bookmarks.json");
+        let (bookmarks, _) = library();
+        fs::write(&path, serde_json::to_string(&bookmarks).unwrap()).unwrap();
+
+        let read = read(&path, BookmarkFormat::Json, &mut vec![]).unwrap();
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].content, bookmarks[0].content);
+        assert!(read[0].folder.is_empty());
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let (bookmarks, folders) = library();
+
+        assert_eq!(round_trip(BookmarkFormat::Csv, "export.csv").0, to_portable(&bookmarks, &folders));
+    }
+
+    #[test]
+    fn directory_round_trips_content_and_folders() {
+        let (read, unsupported) = round_trip(BookmarkFormat::Directory, "export");
+
+        assert!(unsupported.is_empty());
+        assert_eq!(read.len(), 2);
+        // Files are read in name order
+        assert_eq!(read[0].content, "SELECT * FROM \"users\";");
+        // Untitled bookmarks are named after their first line
+        assert_eq!(read[0].title.as_deref(), Some("SELECT _ FROM _users__"));
+        assert!(read[0].folder.is_empty());
+        assert_eq!(read[1].content, "Hello,\nhow are you?");
+        assert_eq!(read[1].title.as_deref(), Some("Greeting"));
+        assert_eq!(read[1].folder, vec!["Work", "Mail"]);
+    }
+
+    #[test]
+    fn directory_export_keeps_bookmarks_with_the_same_title() {
+        let dir = TempDir::new();
+        let bookmarks = vec![Bookmark::new("same".into()), Bookmark::new("same".into())];
+
+        write(&dir.0, BookmarkFormat::Directory, &bookmarks, &[]).unwrap();
+
+        assert!(dir.0.join("same.txt").exists());
+        assert!(dir.0.join("same (2).txt").exists());
+    }
+
+    #[test]
+    fn espanso_round_trips_content_and_labels() {
+        let (read, _) = round_trip(BookmarkFormat::Espanso, "clipbored.yml");
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].content, "Hello,\nhow are you?");
+        assert_eq!(read[0].title.as_deref(), Some("Greeting"));
+        // Without a label the trigger becomes the title
+        assert_eq!(read[1].content, "SELECT * FROM \"users\";");
+        assert_eq!(read[1].title.as_deref(), Some(":select-_-from-_users__"));
+    }
+
+    #[test]
+    fn espanso_lists_matches_it_cannot_import() {
+        let dir = TempDir::new();
+        let path = dir.0.join("base.yml");
+        fs::write(
+            &path,
+            "matches:\n  - triggers: [\":hi\", \":hello\"]\n    replace: Hi there\n  - trigger: \":form\"\n    form: \"Name: [[name]]\"\n",
+        )
+        .unwrap();
+        let mut unsupported = vec![];
+
+        let read = read(&path, BookmarkFormat::Espanso, &mut unsupported).unwrap();
+
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].content, "Hi there");
+        assert_eq!(read[0].title.as_deref(), Some(":hi"));
+        assert_eq!(unsupported, vec![":form"]);
+    }
+
+    fn imported(content: &str, folder: &[&str]) -> PortableBookmark {
+        PortableBookmark {
+            content: content.into(),
+            title: Some(format!("Imported {}", content)),
+            folder: folder.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn merge_into(bookmarks: &mut Vec<Bookmark>, duplicates: DuplicatePolicy) -> ImportReport {
+        let mut report = ImportReport::default();
+        merge(
+            bookmarks,
+            &mut vec![],
+            vec![imported("existing", &[]), imported("new", &[])],
+            duplicates,
+            &mut report,
+        );
+
+        report
+    }
+
+    #[test]
+    fn merge_skips_duplicates() {
+        let mut bookmarks = vec![Bookmark::new("existing".into())];
+
+        let report = merge_into(&mut bookmarks, DuplicatePolicy::Skip);
+
+        assert_eq!((report.added, report.replaced, report.skipped), (1, 0, 1));
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].title, None);
+    }
+
+    #[test]
+    fn merge_replaces_duplicates_keeping_id_and_hotkey() {
+        let existing = Bookmark {
+            hotkey: Some("CmdOrCtrl+Shift+1".into()),
+            ..Bookmark::new("existing".into())
+        };
+        let mut bookmarks = vec![existing.clone()];
+
+        let report = merge_into(&mut bookmarks, DuplicatePolicy::Replace);
+
+        assert_eq!((report.added, report.replaced, report.skipped), (1, 1, 0));
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].id, existing.id);
+        assert_eq!(bookmarks[0].hotkey, existing.hotkey);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Imported existing"));
+    }
+
+    #[test]
+    fn merge_can_keep_both() {
+        let mut bookmarks = vec![Bookmark::new("existing".into())];
+
+        let report = merge_into(&mut bookmarks, DuplicatePolicy::KeepBoth);
+
+        assert_eq!((report.added, report.replaced, report.skipped), (2, 0, 0));
+        assert_eq!(bookmarks.len(), 3);
+    }
+
+    #[test]
+    fn merge_creates_missing_folders_once() {
+        let mut bookmarks = vec![];
+        let mut folders = vec![folder("work", "Work", None)];
+        let mut report = ImportReport::default();
+
+        merge(
+            &mut bookmarks,
+            &mut folders,
+            vec![imported("a", &["Work", "Mail"]), imported("b", &["Work", "Mail"]), imported("c", &["Work"])],
+            DuplicatePolicy::Skip,
+            &mut report,
+        );
+
+        assert_eq!(report.folders_created, 1);
+        assert_eq!(folders.len(), 2);
+        assert_eq!(folders[1].parent_id.as_deref(), Some("work"));
+        assert_eq!(bookmarks[0].folder_id, Some(folders[1].id.clone()));
+        assert_eq!(bookmarks[1].folder_id, bookmarks[0].folder_id);
+        assert_eq!(bookmarks[2].folder_id.as_deref(), Some("work"));
+    }
+}
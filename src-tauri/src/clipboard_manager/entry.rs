@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tauri::{image::Image, AppHandle, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::storage::Vault;
+
+/// Directory (inside app_local_data_dir) where copied images are kept as PNG files
+const IMAGES_DIR: &str = "images";
+
+/// A single clipboard history item, in the format it was copied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ClipboardEntry {
+    Text {
+        text: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Html {
+        html: String,
+        alt_text: Option<String>,
+    },
+    /// An image stored as `images/<hash>.png`, so copying the same picture twice
+    /// reuses the file.
+    Image {
+        hash: String,
+        width: u32,
+        height: u32,
+    },
+    /// A list of copied files (text/uri-list on Linux)
+    FileList {
+        paths: Vec<PathBuf>,
+    },
+}
+
+impl ClipboardEntry {
+    /// Reads the current clipboard contents, preferring the richest format available.
+    ///
+    /// Must not be called from the main thread (see tauri_plugin_clipboard_manager::Clipboard::read_text).
+    pub fn from_clipboard(app: &AppHandle) -> Option<ClipboardEntry> {
+        let clipboard = app.clipboard();
+
+        if let Ok(mut native) = arboard::Clipboard::new() {
+            if let Ok(paths) = native.get().file_list() {
+                if !paths.is_empty() {
+                    return Some(ClipboardEntry::FileList { paths });
+                }
+            }
+        }
+
+        if let Ok(image) = clipboard.read_image() {
+            match store_image(app, &image) {
+                Ok(entry) => return Some(entry),
+                Err(e) => eprintln!("Failed to store clipboard image: {}", e),
+            }
+        }
+
+        let text = clipboard.read_text().ok();
+
+        if let Ok(mut native) = arboard::Clipboard::new() {
+            if let Ok(html) = native.get().html() {
+                return Some(ClipboardEntry::Html { html, alt_text: text });
+            }
+        }
+
+        text.map(|text| ClipboardEntry::Text { text })
+    }
+
+    /// Puts the entry back on the clipboard in its original format. The clipboard
+    /// listener is told, so the write is not taken for a copy.
+    pub fn restore(&self, app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+        let restored = app.state::<Arc<RwLock<RestoredEntry>>>();
+        *restored.inner().write().unwrap() = RestoredEntry(Some(self.clone()));
+
+        let result = self.write_to_clipboard(app);
+        if result.is_err() {
+            *restored.inner().write().unwrap() = RestoredEntry(None);
+        }
+
+        result
+    }
+
+    fn write_to_clipboard(&self, app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+        let clipboard = app.clipboard();
+
+        match self {
+            ClipboardEntry::Text { text } => clipboard.write_text(text.as_str())?,
+            ClipboardEntry::Html { html, alt_text } => {
+                clipboard.write_html(html.as_str(), alt_text.as_deref())?
+            }
+            ClipboardEntry::Image { hash, .. } => {
+                let vault = app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap();
+                let bytes = vault.read(&image_path(app, hash))?;
+                drop(vault);
+                let png = image::load_from_memory_with_format(&bytes, image::ImageFormat::Png)?.to_rgba8();
+                let (width, height) = png.dimensions();
+                clipboard.write_image(&Image::new_owned(png.into_raw(), width, height))?;
+            }
+            ClipboardEntry::FileList { paths } => {
+                arboard::Clipboard::new()?.set().file_list(paths.as_slice())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Same name as the serialized `kind` tag.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClipboardEntry::Text { .. } => "text",
+            ClipboardEntry::Html { .. } => "html",
+            ClipboardEntry::Image { .. } => "image",
+            ClipboardEntry::FileList { .. } => "fileList",
+        }
+    }
+
+    /// Text representation used by bookmarks, if the entry has one.
+    pub fn text_content(&self) -> Option<String> {
+        match self {
+            ClipboardEntry::Text { text } => Some(text.clone()),
+            ClipboardEntry::Html { html, alt_text } => {
+                Some(alt_text.clone().unwrap_or_else(|| html.clone()))
+            }
+            ClipboardEntry::Image { .. } => None,
+            ClipboardEntry::FileList { paths } => Some(
+                paths
+                    .iter()
+                    .map(|path| path.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        }
+    }
+
+    /// Size of the copied data. Images count their decoded RGBA bytes and have no chars.
+    pub fn size(&self) -> EntrySize {
+        let bytes = match self {
+            ClipboardEntry::Text { text } => text.len(),
+            ClipboardEntry::Html { html, .. } => html.len(),
+            ClipboardEntry::Image { width, height, .. } => *width as usize * *height as usize * 4,
+            ClipboardEntry::FileList { .. } => self.text_content().unwrap_or_default().len(),
+        };
+        let chars = self.text_content().map(|text| text.chars().count()).unwrap_or(0);
+
+        EntrySize { bytes, chars }
+    }
+
+    /// Human readable label used by the tray menu.
+    pub fn preview(&self) -> String {
+        match self {
+            ClipboardEntry::Image { width, height, .. } => format!("[Image {}x{}]", width, height),
+            ClipboardEntry::FileList { paths } if paths.len() > 1 => {
+                format!("[{} files] {}", paths.len(), file_name(&paths[0]))
+            }
+            ClipboardEntry::FileList { paths } => format!("[File] {}", file_name(&paths[0])),
+            _ => self.text_content().unwrap_or_default(),
+        }
+    }
+}
+
+/// The entry clipbored last put on the clipboard itself, to paste it.
+#[derive(Default)]
+pub struct RestoredEntry(Option<ClipboardEntry>);
+
+impl RestoredEntry {
+    /// Whether the clipboard change showing `entry` comes from the last restore.
+    /// Only matches once, so copying the same thing afterwards counts again.
+    pub fn take_if(&mut self, entry: &ClipboardEntry) -> bool {
+        if self.0.as_ref() != Some(entry) {
+            return false;
+        }
+        self.0 = None;
+
+        true
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EntrySize {
+    pub bytes: usize,
+    pub chars: usize,
+}
+
+impl From<String> for ClipboardEntry {
+    fn from(text: String) -> Self {
+        ClipboardEntry::Text { text }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+fn images_dir(app: &AppHandle) -> PathBuf {
+    app.path().app_local_data_dir().unwrap().join(IMAGES_DIR)
+}
+
+fn image_path(app: &AppHandle, hash: &str) -> PathBuf {
+    images_dir(app).join(format!("{}.png", hash))
+}
+
+fn store_image(
+    app: &AppHandle,
+    image: &Image<'_>,
+) -> Result<ClipboardEntry, Box<dyn std::error::Error>> {
+    let hash = format!("{:x}", Sha256::digest(image.rgba()));
+    let path = image_path(app, &hash);
+
+    if !path.exists() {
+        fs::create_dir_all(images_dir(app))?;
+        let png = image::RgbaImage::from_raw(image.width(), image.height(), image.rgba().to_vec())
+            .ok_or("Clipboard image has an invalid size")?;
+        let mut bytes = Cursor::new(Vec::new());
+        png.write_to(&mut bytes, image::ImageFormat::Png)?;
+
+        let vault = app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap();
+        vault.write(&path, bytes.get_ref())?;
+    }
+
+    Ok(ClipboardEntry::Image {
+        hash,
+        width: image.width(),
+        height: image.height(),
+    })
+}
+
+/// Deletes stored images that are no longer referenced by any history entry.
+pub fn remove_orphan_images<'a>(
+    app: &AppHandle,
+    entries: impl Iterator<Item = &'a ClipboardEntry>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = images_dir(app);
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let in_use: Vec<PathBuf> = entries
+        .filter_map(|entry| match entry {
+            ClipboardEntry::Image { hash, .. } => Some(image_path(app, hash)),
+            _ => None,
+        })
+        .collect();
+
+    for file in fs::read_dir(dir)? {
+        let path = file?.path();
+        if !in_use.contains(&path) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads (and decrypts) every stored image, so they can be written back under a new key.
+pub fn read_stored_images(
+    app: &AppHandle,
+    vault: &Vault,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, Box<dyn std::error::Error>> {
+    let dir = images_dir(app);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut images = Vec::new();
+    for file in fs::read_dir(dir)? {
+        let path = file?.path();
+        let contents = vault.read_for_rekey(&path)?;
+        images.push((path, contents));
+    }
+
+    Ok(images)
+}
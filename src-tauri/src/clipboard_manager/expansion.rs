@@ -0,0 +1,376 @@
+//! Text expansion: typing a bookmark's abbreviation (e.g. `;sig`) anywhere erases
+//! it and pastes the bookmark in its place.
+
+use rdev::{Event, EventType, Key};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use super::handlers::erase_typed;
+use super::source::active_window_class;
+use crate::commands::{paste_in_focused_window, AppConfig, Bookmark};
+
+/// Characters kept from what was typed, more than any abbreviation needs.
+const MAX_TYPED: usize = 64;
+
+/// What completes an abbreviation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ExpansionTrigger {
+    /// Expand as soon as the last character is typed
+    #[default]
+    Immediate,
+    Space,
+    Tab,
+    Enter,
+}
+
+impl ExpansionTrigger {
+    fn key(&self) -> Option<Key> {
+        match self {
+            ExpansionTrigger::Immediate => None,
+            ExpansionTrigger::Space => Some(Key::Space),
+            ExpansionTrigger::Tab => Some(Key::Tab),
+            ExpansionTrigger::Enter => Some(Key::Return),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExpansionConfig {
+    /// Abbreviations are left as typed while paused
+    pub paused: bool,
+    pub trigger: ExpansionTrigger,
+    /// Window classes where abbreviations are never expanded, e.g. terminals
+    pub disabled_apps: Vec<String>,
+    /// If not empty, abbreviations are only expanded in these window classes
+    pub enabled_apps: Vec<String>,
+}
+
+impl ExpansionConfig {
+    /// Whether abbreviations are expanded in the window with class `app`. When the
+    /// window can't be told (e.g. outside X11), they are only expanded if there is
+    /// no list of enabled apps to respect.
+    fn is_enabled_for(&self, app: Option<&str>) -> bool {
+        let Some(app) = app else {
+            return self.enabled_apps.is_empty();
+        };
+        let listed = |apps: &[String]| apps.iter().any(|x| x.eq_ignore_ascii_case(app));
+
+        !listed(&self.disabled_apps) && (self.enabled_apps.is_empty() || listed(&self.enabled_apps))
+    }
+}
+
+/// Checks a new abbreviation against the other bookmarks' ones. With the immediate
+/// trigger, an abbreviation that starts with another one could never be typed.
+pub fn check_abbreviation(
+    abbreviation: &str,
+    bookmark_id: &str,
+    bookmarks: &[Bookmark],
+    trigger: ExpansionTrigger,
+) -> Result<(), String> {
+    if abbreviation.chars().any(char::is_whitespace) {
+        return Err("Abbreviations can't contain spaces".into());
+    }
+
+    for other in bookmarks.iter().filter(|b| b.id != bookmark_id) {
+        let Some(other_abbreviation) = other.abbreviation.as_deref() else {
+            continue;
+        };
+        let overlaps = trigger == ExpansionTrigger::Immediate
+            && (abbreviation.starts_with(other_abbreviation) || other_abbreviation.starts_with(abbreviation));
+        if other_abbreviation == abbreviation || overlaps {
+            return Err(format!(
+                "{} conflicts with {}, the abbreviation of \"{}\"",
+                abbreviation,
+                other_abbreviation,
+                other.label()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The keyboard listener. It is only started once expansion is on and a bookmark
+/// has an abbreviation. rdev can't end `listen`, so after that it stays hooked
+/// but ignores every key while it is not `active`.
+#[derive(Default)]
+pub struct ExpansionListener {
+    started: bool,
+    active: Arc<AtomicBool>,
+}
+
+/// Whether typed keys have to be read at all.
+fn is_needed(config: &ExpansionConfig, bookmarks: &[Bookmark]) -> bool {
+    !config.paused && bookmarks.iter().any(|b| b.abbreviation.is_some())
+}
+
+/// Starts or idles the keyboard listener after the expansion settings or the
+/// abbreviations changed.
+pub fn sync_listener(app: &AppHandle) {
+    let config = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().expansion.clone();
+    let needed = is_needed(&config, &app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().read().unwrap());
+
+    let state = app.state::<Arc<RwLock<ExpansionListener>>>();
+    let mut listener = state.inner().write().unwrap();
+    listener.active.store(needed, Ordering::SeqCst);
+    if needed && !listener.started {
+        listener.started = true;
+        start_listener(app.clone(), listener.active.clone());
+    }
+}
+
+/// Listens to the keyboard on its own thread for the rest of the app's lifetime.
+fn start_listener(app: AppHandle, active: Arc<AtomicBool>) {
+    std::thread::spawn(move || {
+        let mut expander = Expander {
+            app,
+            active,
+            typed: Typed::default(),
+            held_modifiers: Vec::new(),
+            expanding: Arc::new(AtomicBool::new(false)),
+        };
+        if let Err(e) = rdev::listen(move |event| expander.handle(event)) {
+            eprintln!("Text expansion is not available: {:?}", e);
+        }
+    });
+}
+
+/// The last MAX_TYPED characters typed since the caret last jumped, e.g. after a
+/// click or an arrow key.
+#[derive(Default)]
+struct Typed(String);
+
+impl Typed {
+    fn push(&mut self, text: &str) {
+        self.0.extend(text.chars().filter(|c| !c.is_control()));
+        if let Some((cut, _)) = self.0.char_indices().rev().nth(MAX_TYPED) {
+            self.0.drain(..=cut);
+        }
+    }
+
+    fn pop(&mut self) {
+        self.0.pop();
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// The bookmark whose abbreviation was just typed, as a word of its own, and
+    /// the abbreviation's length in characters. The longest abbreviation wins.
+    fn find_abbreviation(&self, bookmarks: &[Bookmark]) -> Option<(String, usize)> {
+        bookmarks
+            .iter()
+            .filter_map(|b| Some((b, b.abbreviation.as_deref()?)))
+            .filter(|(_, abbreviation)| {
+                let Some(before) = self.0.strip_suffix(abbreviation) else {
+                    return false;
+                };
+                before.chars().last().is_none_or(|c| !c.is_alphanumeric())
+            })
+            .max_by_key(|(_, abbreviation)| abbreviation.len())
+            .map(|(b, abbreviation)| (b.id.clone(), abbreviation.chars().count()))
+    }
+}
+
+struct Expander {
+    app: AppHandle,
+    /// Cleared while no key has to be read, see ExpansionListener
+    active: Arc<AtomicBool>,
+    typed: Typed,
+    /// Ctrl, Alt and Meta keys that are down: keys pressed with them are shortcuts, not text
+    held_modifiers: Vec<Key>,
+    /// Set while an abbreviation is erased and pasted over, so our own keys are not read
+    expanding: Arc<AtomicBool>,
+}
+
+impl Expander {
+    fn handle(&mut self, event: Event) {
+        if !self.active.load(Ordering::SeqCst) {
+            self.typed.clear();
+            self.held_modifiers.clear();
+            return;
+        }
+        if self.expanding.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let key = match event.event_type {
+            EventType::KeyPress(key) => key,
+            EventType::KeyRelease(key) => {
+                self.held_modifiers.retain(|x| *x != key);
+                return;
+            }
+            EventType::ButtonPress(_) => {
+                self.typed.clear();
+                return;
+            }
+            _ => return,
+        };
+
+        if is_shortcut_modifier(key) {
+            if !self.held_modifiers.contains(&key) {
+                self.held_modifiers.push(key);
+            }
+            return;
+        }
+        if !self.held_modifiers.is_empty() {
+            self.typed.clear();
+            return;
+        }
+
+        let config = self.app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().expansion.clone();
+        if config.paused {
+            self.typed.clear();
+            return;
+        }
+
+        if Some(key) == config.trigger.key() {
+            if let Some((id, length)) = self.find_abbreviation() {
+                // The trigger key was typed too
+                self.expand(&config, id, length + 1);
+            }
+            self.typed.clear();
+            return;
+        }
+
+        match key {
+            Key::Backspace => {
+                self.typed.pop();
+            }
+            Key::Return | Key::KpReturn | Key::Tab | Key::Escape | Key::Delete | Key::UpArrow | Key::DownArrow
+            | Key::LeftArrow | Key::RightArrow | Key::Home | Key::End | Key::PageUp | Key::PageDown => {
+                self.typed.clear();
+            }
+            _ => {
+                self.typed.push(&event.name.unwrap_or_default());
+
+                if config.trigger == ExpansionTrigger::Immediate {
+                    if let Some((id, length)) = self.find_abbreviation() {
+                        self.expand(&config, id, length);
+                        self.typed.clear();
+                    }
+                }
+            }
+        }
+    }
+
+    fn find_abbreviation(&self) -> Option<(String, usize)> {
+        let bookmarks = self.app.state::<Arc<RwLock<Vec<Bookmark>>>>();
+        let bookmarks = bookmarks.inner().read().unwrap();
+
+        self.typed.find_abbreviation(&bookmarks)
+    }
+
+    /// Erases `erase` characters and pastes the bookmark, on another thread so the
+    /// listener keeps receiving (and ignoring) the keys it sends.
+    fn expand(&self, config: &ExpansionConfig, id: String, erase: usize) {
+        if !config.is_enabled_for(active_window_class().as_deref()) {
+            return;
+        }
+
+        let app = self.app.clone();
+        let expanding = self.expanding.clone();
+        expanding.store(true, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            // Give the key that completed the abbreviation time to be released
+            std::thread::sleep(Duration::from_millis(50));
+            let result = erase_typed(erase)
+                .map_err(|e| e.to_string())
+                .and_then(|_| paste_in_focused_window(app, id));
+            if let Err(e) = result {
+                eprintln!("Failed to expand abbreviation: {}", e);
+            }
+            expanding.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+fn is_shortcut_modifier(key: Key) -> bool {
+    matches!(key, Key::ControlLeft | Key::ControlRight | Key::Alt | Key::MetaLeft | Key::MetaRight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(abbreviation: &str) -> Bookmark {
+        Bookmark {
+            abbreviation: Some(abbreviation.into()),
+            ..Bookmark::new(format!("expanded {}", abbreviation))
+        }
+    }
+
+    fn typed(text: &str) -> Typed {
+        let mut typed = Typed::default();
+        typed.push(text);
+        typed
+    }
+
+    #[test]
+    fn keeps_the_last_characters_typed() {
+        let mut typed = typed("héllo\u{8}\t");
+        assert_eq!(typed.0, "héllo");
+
+        typed.pop();
+        typed.push(&"é".repeat(MAX_TYPED));
+        assert_eq!(typed.0.chars().count(), MAX_TYPED);
+        assert!(typed.0.chars().all(|c| c == 'é'));
+
+        typed.clear();
+        assert_eq!(typed.0, "");
+    }
+
+    #[test]
+    fn abbreviations_match_as_words_of_their_own() {
+        let bookmarks = vec![bookmark(";sig"), bookmark("addr")];
+        let found = |text| {
+            let (id, length) = typed(text).find_abbreviation(&bookmarks)?;
+            let bookmark = bookmarks.iter().find(|b| b.id == id)?;
+            Some((bookmark.abbreviation.clone()?, length))
+        };
+
+        assert_eq!(found("see ;sig"), Some((";sig".into(), 4)));
+        assert_eq!(found(";sig"), Some((";sig".into(), 4)));
+        assert_eq!(found("(addr"), Some(("addr".into(), 4)));
+        assert_eq!(found("myaddr"), None);
+        assert_eq!(found(";si"), None);
+    }
+
+    #[test]
+    fn the_longest_abbreviation_wins() {
+        let bookmarks = vec![bookmark("gm"), bookmark(";gm"), bookmark("über")];
+
+        let (id, length) = typed("x ;gm").find_abbreviation(&bookmarks).unwrap();
+        assert_eq!((id.as_str(), length), (bookmarks[1].id.as_str(), 3));
+
+        let (id, length) = typed("über").find_abbreviation(&bookmarks).unwrap();
+        assert_eq!((id.as_str(), length), (bookmarks[2].id.as_str(), 4));
+    }
+
+    #[test]
+    fn the_listener_is_only_needed_for_abbreviations_while_not_paused() {
+        let config = ExpansionConfig::default();
+        let paused = ExpansionConfig { paused: true, ..Default::default() };
+
+        assert!(is_needed(&config, &[Bookmark::new("plain".into()), bookmark(";sig")]));
+        assert!(!is_needed(&config, &[Bookmark::new("plain".into())]));
+        assert!(!is_needed(&paused, &[bookmark(";sig")]));
+    }
+
+    #[test]
+    fn overlapping_abbreviations_are_refused_with_the_immediate_trigger() {
+        let bookmarks = vec![bookmark(";sig")];
+
+        assert!(check_abbreviation(";signature", "new", &bookmarks, ExpansionTrigger::Immediate).is_err());
+        assert!(check_abbreviation(";signature", "new", &bookmarks, ExpansionTrigger::Space).is_ok());
+        assert!(check_abbreviation(";sig", "new", &bookmarks, ExpansionTrigger::Space).is_err());
+        assert!(check_abbreviation(";sig", &bookmarks[0].id, &bookmarks, ExpansionTrigger::Immediate).is_ok());
+        assert!(check_abbreviation("; sig", "new", &bookmarks, ExpansionTrigger::Space).is_err());
+    }
+}
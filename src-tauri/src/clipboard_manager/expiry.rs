@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::filter::Pattern;
+use super::history::{now_millis, ClipboardHistory, HistoryItem};
+use super::queue::PasteQueue;
+use super::snippet_dir::merged_bookmarks;
+use super::tray::setup_tray_menu;
+use crate::commands::{save_history_to_file, AppConfig, Bookmark};
+
+/// How often the background task looks for entries that are too old
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Overrides the global max age for the entries it matches. Every condition that
+/// is set must match; a rule with no conditions matches everything.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpiryRule {
+    pub name: String,
+    /// Entry kind ("text", "html", "image", "fileList")
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// Window class of the application the entry was copied from
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Regex matched against the text of the entry
+    #[serde(default)]
+    pub pattern: Option<Pattern>,
+    /// None keeps matching entries until they are evicted by max_items
+    pub max_age_seconds: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExpiryConfig {
+    /// Applies to entries no rule matched. None disables time-based expiry.
+    pub max_age_seconds: Option<u64>,
+    /// Checked in order, the first matching rule wins
+    pub rules: Vec<ExpiryRule>,
+}
+
+impl ExpiryConfig {
+    /// Max age for `item`, from the first matching rule or the global setting.
+    pub fn max_age(&self, item: &HistoryItem) -> Option<u64> {
+        match self.rules.iter().find(|rule| rule.matches(item)) {
+            Some(rule) => rule.max_age_seconds,
+            None => self.max_age_seconds,
+        }
+    }
+}
+
+impl ExpiryRule {
+    fn matches(&self, item: &HistoryItem) -> bool {
+        if self.kind.as_ref().is_some_and(|kind| kind != item.entry.kind()) {
+            return false;
+        }
+        if self.source.is_some() && self.source != item.source {
+            return false;
+        }
+        match &self.pattern {
+            Some(pattern) => item.entry.text_content().is_some_and(|text| pattern.is_match(&text)),
+            None => true,
+        }
+    }
+}
+
+/// Pinned, queued and bookmarked entries (snippet directory included) are kept no
+/// matter how old they are.
+fn is_exempt(item: &HistoryItem, bookmarks: &[Bookmark], queue: &PasteQueue) -> bool {
+    item.pinned || queue.contains(&item.id) || bookmarks.iter().any(|b| {
+        b.history_id.as_deref() == Some(item.id.as_str())
+            || item.entry.text_content().as_deref() == Some(b.content.as_str())
+    })
+}
+
+/// Removes entries past their max age or their sensitive-content expiry time, then
+/// saves the history, refreshes the tray and tells the UI. Does nothing if no entry expired.
+pub fn prune_history(app: &AppHandle) {
+    let bookmarks = merged_bookmarks(app);
+    let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
+    let expiry = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().expiry.clone();
+    // The clipboard listener locks the queue after the history, as here
+    let queue = app.state::<Arc<RwLock<PasteQueue>>>().inner().read().unwrap();
+
+    let now = now_millis();
+    let removed_old = history.remove_where(|item| {
+        !is_exempt(item, &bookmarks, &queue)
+            && expiry
+                .max_age(item)
+                .is_some_and(|max_age| now.saturating_sub(item.last_copied_at) > max_age * 1000)
+    });
+    drop(queue);
+    let removed_expired = history.remove_expired();
+    if !removed_old && !removed_expired {
+        return;
+    }
+
+    if let Err(e) = save_history_to_file(app, &history) {
+        eprintln!("Failed to save clipboard history: {}", e);
+    }
+    drop(history);
+
+    setup_tray_menu(app, Some(true));
+    if let Err(e) = app.emit_to("main", "clipboard-updated", ()) {
+        eprintln!("Error emitting clipboard-updated: {}", e);
+    }
+}
+
+/// Starts the background task that prunes old entries every PRUNE_INTERVAL.
+pub fn start_pruning(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        prune_history(&app);
+        std::thread::sleep(PRUNE_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard_manager::entry::ClipboardEntry;
+
+    fn item(text: &str) -> HistoryItem {
+        ClipboardEntry::from(text.to_string()).into()
+    }
+
+    fn rule(name: &str, max_age_seconds: Option<u64>) -> ExpiryRule {
+        ExpiryRule {
+            name: name.into(),
+            kind: None,
+            source: None,
+            pattern: None,
+            max_age_seconds,
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_sets_the_max_age() {
+        let config = ExpiryConfig {
+            max_age_seconds: Some(3600),
+            rules: vec![
+                ExpiryRule { kind: Some("image".into()), ..rule("images", Some(60)) },
+                ExpiryRule { pattern: Some(Pattern::new("^\\d{6}$")), ..rule("codes", Some(30)) },
+                ExpiryRule { source: Some("KeePassXC".into()), ..rule("keepass", None) },
+                rule("everything else", Some(10)),
+            ],
+        };
+        let image = HistoryItem::from(ClipboardEntry::Image { hash: "abc".into(), width: 1, height: 1 });
+        let from_keepass = HistoryItem { source: Some("KeePassXC".into()), ..item("hunter2") };
+
+        assert_eq!(config.max_age(&image), Some(60));
+        assert_eq!(config.max_age(&item("123456")), Some(30));
+        assert_eq!(config.max_age(&from_keepass), None);
+        assert_eq!(config.max_age(&item("hello")), Some(10));
+        assert_eq!(ExpiryConfig { max_age_seconds: Some(3600), rules: vec![] }.max_age(&item("hello")), Some(3600));
+    }
+
+    #[test]
+    fn pinned_queued_and_bookmarked_entries_are_exempt() {
+        let pinned = HistoryItem { pinned: true, ..item("pinned") };
+        let queued = item("queued");
+        let by_id = item("toggled");
+        let by_content = item("from the snippet directory");
+        let bookmarks = vec![
+            Bookmark { history_id: Some(by_id.id.clone()), ..Bookmark::new("toggled".into()) },
+            Bookmark { file: Some("signature.txt".into()), ..Bookmark::new("from the snippet directory".into()) },
+        ];
+        let mut queue = PasteQueue::default();
+        queue.toggle();
+        queue.push(&queued.id);
+
+        for item in [&pinned, &queued, &by_id, &by_content] {
+            assert!(is_exempt(item, &bookmarks, &queue), "{}", item.entry.preview());
+        }
+        assert!(!is_exempt(&item("anything else"), &bookmarks, &queue));
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+use super::history::now_millis;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// Order of the bookmarks and history entries in the tray and `get_bookmarks`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SortMode {
+    /// Bookmarks in list order, history from the latest copy
+    #[default]
+    Default,
+    /// Most pasted first, recent pastes weighing more than old ones
+    Frecency,
+}
+
+/// How many times something was pasted, weighted by how long ago it was last
+/// pasted, so things that stopped being used sink over time.
+pub fn score(paste_count: u32, last_pasted_at: Option<u64>, now: u64) -> u64 {
+    let Some(last_pasted_at) = last_pasted_at else {
+        return 0;
+    };
+    let weight = match now.saturating_sub(last_pasted_at) / DAY_MILLIS {
+        0..=3 => 100,
+        4..=13 => 70,
+        14..=30 => 50,
+        31..=89 => 30,
+        _ => 10,
+    };
+
+    paste_count as u64 * weight
+}
+
+/// Sorts `items` by frecency, highest first. Items with the same score (e.g. never
+/// pasted) keep their order.
+pub fn sort_by_frecency<T>(items: &mut [T], usage: impl Fn(&T) -> (u32, Option<u64>)) {
+    let now = now_millis();
+    items.sort_by_cached_key(|item| {
+        let (paste_count, last_pasted_at) = usage(item);
+        Reverse(score(paste_count, last_pasted_at, now))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000 * DAY_MILLIS;
+
+    fn days_ago(days: u64) -> Option<u64> {
+        Some(NOW - days * DAY_MILLIS)
+    }
+
+    #[test]
+    fn never_pasted_scores_nothing() {
+        assert_eq!(score(0, None, NOW), 0);
+        assert_eq!(score(5, None, NOW), 0);
+    }
+
+    #[test]
+    fn recent_pastes_weigh_more() {
+        let scores: Vec<u64> = [0, 3, 4, 13, 14, 30, 31, 89, 90, 365]
+            .into_iter()
+            .map(|days| score(2, days_ago(days), NOW))
+            .collect();
+
+        assert_eq!(scores, vec![200, 200, 140, 140, 100, 100, 60, 60, 20, 20]);
+    }
+
+    #[test]
+    fn frequent_old_pastes_can_outrank_a_recent_one() {
+        assert!(score(10, days_ago(60), NOW) > score(1, days_ago(0), NOW));
+        assert!(score(1, days_ago(0), NOW) > score(3, days_ago(100), NOW));
+    }
+
+    #[test]
+    fn pastes_in_the_future_count_as_recent() {
+        assert_eq!(score(1, Some(NOW + DAY_MILLIS), NOW), 100);
+    }
+
+    #[test]
+    fn sorts_highest_first_and_keeps_ties_in_order() {
+        let now = now_millis();
+        let mut items = vec![
+            ("never", 0, None),
+            ("old", 5, Some(now - 100 * DAY_MILLIS)),
+            ("also never", 0, None),
+            ("recent", 2, Some(now)),
+        ];
+
+        sort_by_frecency(&mut items, |(_, paste_count, last_pasted_at)| (*paste_count, *last_pasted_at));
+
+        let names: Vec<&str> = items.iter().map(|(name, _, _)| *name).collect();
+        assert_eq!(names, vec!["recent", "old", "never", "also never"]);
+    }
+}
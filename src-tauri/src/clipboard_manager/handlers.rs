@@ -1,38 +1,55 @@
-use std::{str::FromStr, sync::{Arc, RwLock}};
+use std::{collections::HashMap, str::FromStr, sync::{Arc, RwLock}};
 use tauri::{menu::MenuEvent, tray::{MouseButton, TrayIcon, TrayIconEvent}, AppHandle, Manager};
-use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut}; // Removed APP_CONFIG and BOOKMARKS
-use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState}; // Removed APP_CONFIG and BOOKMARKS
 
 use crate::commands::{AppConfig, Bookmark};
+use crate::validation::FieldError;
 use super::history::ClipboardHistory;
+use super::transform::Transform;
 
 pub fn handle_tray_menu_event(app: &AppHandle, event: MenuEvent) {
-    let clipboard = app.clipboard();
-
-    
     match event.id.0.as_str() {
         "quit" => std::process::exit(0),
         "show" => app.get_webview_window("main").unwrap().show().unwrap(),
-        item_id if item_id.starts_with("item_bm_") => {
-            dbg!("Vem do bookmark");
-            let bookmark_reader = app.state::<Arc<RwLock<Vec<Bookmark>>>>();
-            let bookmarks = bookmark_reader.read().unwrap();
-            if let Ok(index) = item_id[8..].parse::<usize>() {
-                if let Some(text) = bookmarks.get(index) {
-                    clipboard.write_text::<String>(text.clone().content).unwrap();
-                    let _ = paste_text();
+        "toggle_paste_queue" => {
+            crate::commands::toggle_paste_queue(app.to_owned());
+        }
+        "paste_next" => {
+            if let Err(e) = crate::commands::paste_next(app.to_owned()) {
+                eprintln!("Failed to paste next: {}", e);
+            }
+        }
+        "toggle_expansion_pause" => {
+            if let Err(e) = crate::commands::toggle_expansion_pause(app.to_owned()) {
+                eprintln!("Failed to toggle text expansion: {}", e);
+            }
+        }
+        "clear_history" => {
+            if let Err(e) = crate::commands::clear_history(app.to_owned(), None) {
+                eprintln!("Failed to clear history: {}", e);
+            }
+        }
+        "toggle_pin_latest" => {
+            let latest = app.state::<Arc<RwLock<ClipboardHistory>>>().read().unwrap().get_items().into_iter().next();
+            if let Some(item) = latest {
+                if let Err(e) = crate::commands::toggle_pin(app.to_owned(), item.id) {
+                    eprintln!("Failed to toggle pin: {}", e);
                 }
             }
-        },
+        }
+        // "transform:<name>:<tray item id>", from the "Paste as…" submenu
+        item_id if item_id.starts_with("transform:") => {
+            let (name, tray_id) = item_id["transform:".len()..].split_once(':').unwrap_or_default();
+            if let (Some(transform), Some(id)) = (Transform::from_name(name), item_key(tray_id)) {
+                if let Err(e) = crate::commands::paste_item(app.to_owned(), id.to_string(), Some(transform), None) {
+                    eprintln!("Failed to paste transformed item: {}", e);
+                }
+            }
+        }
         item_id if item_id.starts_with("item_") => {
-            dbg!("Vem do clipboard");
-            let history_reader = app.state::<Arc<RwLock<ClipboardHistory>>>();
-            let history = history_reader.read().unwrap();
-            if let Ok(index) = item_id[5..].parse::<usize>() {
-                let items = history.get_items();
-                if let Some(text) = items.get(index) {
-                    clipboard.write_text::<String>(text.clone()).unwrap();
-                    let _ = paste_text();
+            if let Some(id) = item_key(item_id) {
+                if let Err(e) = crate::commands::paste_item(app.to_owned(), id.to_string(), None, None) {
+                    eprintln!("Failed to paste item: {}", e);
                 }
             }
         }
@@ -40,16 +57,20 @@ pub fn handle_tray_menu_event(app: &AppHandle, event: MenuEvent) {
     }
 }
 
-pub fn paste_text() -> Result<(), rdev::SimulateError> {
-    use rdev::{simulate, EventType, Key};
-    use std::{time, thread};
-    let send = |event: &EventType| -> Result<_, rdev::SimulateError> {
-        simulate(event).unwrap();
-        thread::sleep(time::Duration::from_millis(10));
-        Ok(())
-    };
+/// History entry or bookmark id from a tray item id (`item_<id>` or `item_bm_<id>`).
+fn item_key(tray_id: &str) -> Option<&str> {
+    tray_id.strip_prefix("item_bm_").or_else(|| tray_id.strip_prefix("item_"))
+}
+
+fn send(event: &rdev::EventType) -> Result<(), rdev::SimulateError> {
+    rdev::simulate(event)?;
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    Ok(())
+}
 
+pub fn paste_text() -> Result<(), rdev::SimulateError> {
     #[cfg(windows)] {
+        use rdev::{EventType, Key};
         // try to go back to the background window
         send(&EventType::KeyPress(Key::Alt))?;
         send(&EventType::KeyPress(Key::Escape))?;
@@ -58,6 +79,13 @@ pub fn paste_text() -> Result<(), rdev::SimulateError> {
         send(&EventType::KeyRelease(Key::Alt))?;
     }
 
+    paste_keys()
+}
+
+/// Sends the paste shortcut to the window that has the focus.
+pub fn paste_keys() -> Result<(), rdev::SimulateError> {
+    use rdev::{EventType, Key};
+
     // try pasting the text
     #[cfg(target_os = "linux")] {
         send(&EventType::KeyPress(Key::ShiftRight))?;
@@ -74,6 +102,18 @@ pub fn paste_text() -> Result<(), rdev::SimulateError> {
     Ok(())
 }
 
+/// Deletes the last `count` typed characters with Backspace.
+pub fn erase_typed(count: usize) -> Result<(), rdev::SimulateError> {
+    use rdev::{EventType, Key};
+
+    for _ in 0..count {
+        send(&EventType::KeyPress(Key::Backspace))?;
+        send(&EventType::KeyRelease(Key::Backspace))?;
+    }
+
+    Ok(())
+}
+
 
 pub fn handle_tray_icon_event(tray_icon: &TrayIcon, event: tauri::tray::TrayIconEvent) {
     match event {
@@ -102,25 +142,29 @@ pub fn handle_tray_icon_event(tray_icon: &TrayIcon, event: tauri::tray::TrayIcon
     }
 }
 
-pub fn register_keyboard_shortcuts(app: &AppHandle, config: &AppConfig) -> Result<(), tauri_plugin_global_shortcut::Error>{
-    let open_key = config.open_shortcut.as_str().replace("Meta", "Super");
-    let bookmark_key = config.bookmark_shortcut.as_str().replace("Meta", "Super");
+/// Registers the app's shortcuts from `config`. A shortcut that is invalid or taken
+/// by another app is skipped, without keeping the others from being registered.
+pub fn register_keyboard_shortcuts(app: &AppHandle, config: &AppConfig) -> Vec<FieldError> {
+    let results = [
+        register_shortcut(app, "openShortcut", &config.open_shortcut, open_shortcut_handler),
+        register_shortcut(app, "bookmarkShortcut", &config.bookmark_shortcut, bookmark_shortcut_handler),
+        register_optional_shortcut(app, "clearShortcut", &config.clear_shortcut, clear_shortcut_handler),
+        register_optional_shortcut(app, "queueShortcut", &config.queue_shortcut, queue_shortcut_handler),
+        register_optional_shortcut(app, "pasteNextShortcut", &config.paste_next_shortcut, paste_next_shortcut_handler),
+    ];
+
+    results.into_iter().filter_map(Result::err).collect()
+}
 
+/// Unregisters the app's shortcuts from `config`, before other ones replace them.
+pub fn unregister_keyboard_shortcuts(app: &AppHandle, config: &AppConfig) {
     let global_shortcut_manager = app.global_shortcut();
-    let open_shortcut = Shortcut::from_str(&open_key).unwrap();
-    let bookmark_shortcut = Shortcut::from_str(&bookmark_key).unwrap();
-    // Unregister old shortcuts
-    if global_shortcut_manager. is_registered(open_shortcut) {
-        global_shortcut_manager.unregister(open_shortcut).unwrap();
-    }
-    if global_shortcut_manager.is_registered(bookmark_shortcut) {
-        global_shortcut_manager.unregister(bookmark_shortcut).unwrap();
+    for (_, _, key) in config_shortcuts(config) {
+        let Ok(shortcut) = parse_shortcut(key) else { continue };
+        if global_shortcut_manager.is_registered(shortcut) {
+            let _ = global_shortcut_manager.unregister(shortcut);
+        }
     }
-
-    global_shortcut_manager.on_shortcut(open_shortcut, crate::clipboard_manager::handlers::open_shortcut_handler)?;
-    global_shortcut_manager.on_shortcut(bookmark_shortcut, crate::clipboard_manager::handlers::bookmark_shortcut_handler)?;
-
-    Ok(())
 }
 
 pub fn open_shortcut_handler<T, U>(app: &'_ AppHandle, _: &'_ T, _: U) {
@@ -134,15 +178,184 @@ pub fn bookmark_shortcut_handler<T, U>(app: &'_ AppHandle, _: &'_ T, _: U) {
 
     drop(history);
     if let Some(last_item) = items.first() {
-        let bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner();
-        let bm = bookmarks.write().unwrap();
-        // Check if item is already bookmarked
-        if let Some(index) = bm.iter().position(|b| b.content == *last_item) {
-            drop(bm);
-            let _ = crate::commands::remove_bookmark(app.to_owned(), index);
-        } else {
-            drop(bm);
-            let _ = crate::commands::add_bookmark(app.to_owned(), last_item.clone());
+        // toggle_bookmark removes the bookmark if this entry was already bookmarked
+        let _ = crate::commands::toggle_bookmark(app.to_owned(), last_item.id.clone());
+    }
+}
+
+/// Registers `key` with `handler`, replacing an earlier registration of the same shortcut.
+fn register_shortcut<F>(app: &AppHandle, field: &str, key: &str, handler: F) -> Result<(), FieldError>
+where
+    F: Fn(&AppHandle, &Shortcut, ShortcutEvent) + Send + Sync + 'static,
+{
+    let shortcut = parse_shortcut(key).map_err(|e| FieldError::new(field, e))?;
+    let global_shortcut_manager = app.global_shortcut();
+    if global_shortcut_manager.is_registered(shortcut) {
+        let _ = global_shortcut_manager.unregister(shortcut);
+    }
+
+    global_shortcut_manager
+        .on_shortcut(shortcut, handler)
+        .map_err(|e| FieldError::new(field, format!("Failed to register {}: {}", key, e)))
+}
+
+/// Registers a shortcut that is only set when the user configured one.
+fn register_optional_shortcut<F>(app: &AppHandle, field: &str, key: &Option<String>, handler: F) -> Result<(), FieldError>
+where
+    F: Fn(&AppHandle, &Shortcut, ShortcutEvent) + Send + Sync + 'static,
+{
+    match key {
+        Some(key) => register_shortcut(app, field, key, handler),
+        None => Ok(()),
+    }
+}
+
+pub fn clear_shortcut_handler(app: &AppHandle, _: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+    if let Err(e) = crate::commands::clear_history(app.to_owned(), None) {
+        eprintln!("Failed to clear history: {}", e);
+    }
+}
+
+pub fn queue_shortcut_handler(app: &AppHandle, _: &Shortcut, event: ShortcutEvent) {
+    if event.state() == ShortcutState::Pressed {
+        crate::commands::toggle_paste_queue(app.to_owned());
+    }
+}
+
+pub fn paste_next_shortcut_handler(app: &AppHandle, _: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+    if let Err(e) = crate::commands::paste_next(app.to_owned()) {
+        eprintln!("Failed to paste next: {}", e);
+    }
+}
+
+/// Global shortcuts registered for bookmarks, by bookmark id.
+#[derive(Default)]
+pub struct BookmarkHotkeys(HashMap<String, Shortcut>);
+
+pub fn parse_shortcut(key: &str) -> Result<Shortcut, String> {
+    Shortcut::from_str(&key.replace("Meta", "Super")).map_err(|e| format!("Invalid shortcut {}: {}", key, e))
+}
+
+/// The app's own shortcuts, with the setting each one comes from and its name.
+pub fn config_shortcuts(config: &AppConfig) -> Vec<(&'static str, &'static str, &str)> {
+    let mut shortcuts = vec![
+        ("openShortcut", "open", config.open_shortcut.as_str()),
+        ("bookmarkShortcut", "bookmark", config.bookmark_shortcut.as_str()),
+    ];
+    for (field, name, key) in [
+        ("clearShortcut", "clear history", &config.clear_shortcut),
+        ("queueShortcut", "paste queue", &config.queue_shortcut),
+        ("pasteNextShortcut", "paste next", &config.paste_next_shortcut),
+    ] {
+        if let Some(key) = key {
+            shortcuts.push((field, name, key.as_str()));
+        }
+    }
+
+    shortcuts
+}
+
+/// Checks that `key` is a valid shortcut that is neither one of the app's shortcuts
+/// nor the hotkey of another bookmark than `bookmark_id`.
+pub fn check_bookmark_hotkey(app: &AppHandle, bookmark_id: &str, key: &str) -> Result<Shortcut, String> {
+    let shortcut = parse_shortcut(key)?;
+
+    let config = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().clone();
+    for (_, name, other) in config_shortcuts(&config) {
+        if parse_shortcut(other).ok() == Some(shortcut) {
+            return Err(format!("{} is already the {} shortcut", key, name));
+        }
+    }
+
+    let bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().read().unwrap();
+    if let Some(other) = bookmark_with_hotkey(&bookmarks, &shortcut).filter(|b| b.id != bookmark_id) {
+        return Err(format!("{} is already the hotkey of \"{}\"", key, other.label()));
+    }
+
+    Ok(shortcut)
+}
+
+/// Checks that none of the app's shortcuts in `config` is taken by a bookmark.
+pub fn check_config_shortcuts(app: &AppHandle, config: &AppConfig) -> Vec<FieldError> {
+    let bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().read().unwrap();
+    let mut errors = Vec::new();
+    for (field, _, key) in config_shortcuts(config) {
+        let Ok(shortcut) = parse_shortcut(key) else { continue };
+        if let Some(bookmark) = bookmark_with_hotkey(&bookmarks, &shortcut) {
+            errors.push(FieldError::new(field, format!("{} is already the hotkey of \"{}\"", key, bookmark.label())));
+        }
+    }
+
+    errors
+}
+
+fn bookmark_with_hotkey<'a>(bookmarks: &'a [Bookmark], shortcut: &Shortcut) -> Option<&'a Bookmark> {
+    bookmarks
+        .iter()
+        .find(|b| b.hotkey.as_deref().and_then(|key| parse_shortcut(key).ok()).as_ref() == Some(shortcut))
+}
+
+/// Registers the hotkeys of the current bookmarks and unregisters the ones of
+/// bookmarks that were removed or changed. Returns the bookmarks whose hotkey
+/// could not be registered, with the reason.
+pub fn sync_bookmark_hotkeys(app: &AppHandle) -> Vec<(String, String)> {
+    let wanted: Vec<(String, Result<Shortcut, String>)> = app
+        .state::<Arc<RwLock<Vec<Bookmark>>>>()
+        .inner()
+        .read()
+        .unwrap()
+        .iter()
+        .filter_map(|b| Some((b.id.clone(), parse_shortcut(b.hotkey.as_deref()?))))
+        .collect();
+
+    let global_shortcut_manager = app.global_shortcut();
+    let hotkeys = app.state::<Arc<RwLock<BookmarkHotkeys>>>();
+    let mut registered = hotkeys.inner().write().unwrap();
+
+    registered.0.retain(|id, shortcut| {
+        let keep = wanted.iter().any(|(wanted_id, wanted)| wanted_id == id && wanted.as_ref() == Ok(&*shortcut));
+        if !keep {
+            let _ = global_shortcut_manager.unregister(*shortcut);
+        }
+        keep
+    });
+
+    let mut failed = Vec::new();
+    for (id, shortcut) in wanted {
+        if registered.0.contains_key(&id) {
+            continue;
+        }
+        let result = shortcut.and_then(|shortcut| {
+            global_shortcut_manager
+                .on_shortcut(shortcut, bookmark_hotkey_handler(id.clone()))
+                .map_err(|e| e.to_string())?;
+            Ok(shortcut)
+        });
+        match result {
+            Ok(shortcut) => {
+                registered.0.insert(id, shortcut);
+            }
+            Err(e) => failed.push((id, e)),
+        }
+    }
+
+    failed
+}
+
+fn bookmark_hotkey_handler(id: String) -> impl Fn(&AppHandle, &Shortcut, ShortcutEvent) + Send + Sync + 'static {
+    move |app, _, event| {
+        // On release, so the hotkey's own keys don't mix with the simulated Ctrl+V
+        if event.state() != ShortcutState::Released {
+            return;
+        }
+        if let Err(e) = crate::commands::paste_in_focused_window(app.to_owned(), id.clone()) {
+            eprintln!("Failed to paste bookmark: {}", e);
         }
     }
 }
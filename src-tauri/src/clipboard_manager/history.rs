@@ -1,56 +1,299 @@
 extern crate clipboard_master;
 use clipboard_master::{CallbackResult, ClipboardHandler};
 
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Manager, Emitter};
-use tauri_plugin_clipboard_manager::ClipboardExt;
 
-pub struct ClipboardHistory(RwLock<VecDeque<String>>, usize);
+use super::entry::{ClipboardEntry, EntrySize, RestoredEntry};
+use super::expiry::prune_history;
+use super::filter::{self, FilterAction};
+use super::queue::PasteQueue;
+use super::source::{clipboard_has_secret_hint, clipboard_owner_class};
+use super::tray::setup_tray_menu;
+use crate::commands::{save_history_to_file, AppConfig};
+
+/// A history entry together with what we know about when and where it was copied.
+/// Timestamps are milliseconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryItem {
+    #[serde(default = "new_id")]
+    pub id: String,
+    pub entry: ClipboardEntry,
+    pub first_copied_at: u64,
+    pub last_copied_at: u64,
+    pub copy_count: u32,
+    pub size: EntrySize,
+    pub source: Option<String>,
+    /// Set by the sensitive-content filter: the content is hidden in the tray and UI
+    #[serde(default)]
+    pub masked: bool,
+    /// Set by the sensitive-content filter: the entry is removed after this time
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Pinned entries are never evicted by max_items or expiry
+    #[serde(default)]
+    pub pinned: bool,
+    /// Times the entry was pasted from the tray or the UI
+    #[serde(default)]
+    pub paste_count: u32,
+    #[serde(default)]
+    pub last_pasted_at: Option<u64>,
+}
+
+impl HistoryItem {
+    pub fn new(entry: ClipboardEntry, source: Option<String>) -> Self {
+        let now = now_millis();
+        HistoryItem {
+            id: new_id(),
+            size: entry.size(),
+            entry,
+            first_copied_at: now,
+            last_copied_at: now,
+            copy_count: 1,
+            source,
+            masked: false,
+            expires_at: None,
+            pinned: false,
+            paste_count: 0,
+            last_pasted_at: None,
+        }
+    }
+}
+
+/// Shown instead of the content of masked entries.
+pub const MASKED_PLACEHOLDER: &str = "••••••••";
+
+impl HistoryItem {
+    /// The entry as the UI may see it: masked content is replaced by the placeholder.
+    pub fn redacted(&self) -> HistoryItem {
+        if !self.masked {
+            return self.clone();
+        }
+
+        HistoryItem {
+            entry: ClipboardEntry::Text {
+                text: MASKED_PLACEHOLDER.into(),
+            },
+            ..self.clone()
+        }
+    }
+}
+
+/// The entries that may be written to history.json. Entries about to expire never
+/// are; masked ones only when the store is encrypted.
+pub fn items_to_save(items: &[HistoryItem], encrypted: bool) -> Vec<&HistoryItem> {
+    items
+        .iter()
+        .filter(|item| item.expires_at.is_none() && (encrypted || !item.masked))
+        .collect()
+}
+
+impl From<ClipboardEntry> for HistoryItem {
+    fn from(entry: ClipboardEntry) -> Self {
+        HistoryItem::new(entry, None)
+    }
+}
+
+/// Unique identifier for history entries and bookmarks, used by commands and tray menu ids.
+pub fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Which entries `clear_history` keeps. Everything else is removed.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ClearOptions {
+    pub keep_pinned: bool,
+    /// Keep this many of the most recent entries
+    pub keep_last: Option<usize>,
+    /// Only remove entries last copied more than this many seconds ago
+    pub older_than_seconds: Option<u64>,
+}
+
+impl Default for ClearOptions {
+    fn default() -> Self {
+        ClearOptions {
+            keep_pinned: true,
+            keep_last: None,
+            older_than_seconds: None,
+        }
+    }
+}
+
+pub struct ClipboardHistory(RwLock<VecDeque<HistoryItem>>, usize);
 
 
 impl ClipboardHistory {
-    pub fn new(limit: usize) -> Self {
-        ClipboardHistory(RwLock::new(VecDeque::with_capacity(limit)), limit)
+    /// Builds the history from items previously saved to disk, newest first.
+    pub fn with_items(limit: usize, items: Vec<HistoryItem>) -> Self {
+        let mut history: VecDeque<HistoryItem> = items.into_iter().collect();
+        evict(&mut history, limit);
+        history.reserve(limit.saturating_sub(history.len()));
+        ClipboardHistory(RwLock::new(history), limit)
     }
 
-    pub fn add_item(&self, item: String) {
+    /// Adds the entry to the front of the history and returns its id. `copied` is
+    /// false when clipbored put the entry on the clipboard itself (a paste), which
+    /// doesn't count as a copy.
+    pub fn add_item(&self, entry: ClipboardEntry, source: Option<String>, copied: bool) -> String {
         let mut history = self.0.write().unwrap();
-        // Remove item if it already exists to avoid duplicates
-        history.retain(|x| x != &item);
+        // Copying the same thing again moves it to the front and keeps its stats
+        let item = match history.iter().position(|x| x.entry == entry) {
+            Some(index) => {
+                let mut item = history.remove(index).unwrap();
+                if copied {
+                    item.last_copied_at = now_millis();
+                    item.copy_count += 1;
+                    if source.is_some() {
+                        item.source = source;
+                    }
+                }
+                item
+            }
+            None if copied => HistoryItem::new(entry, source),
+            // E.g. a pasted bookmark
+            None => HistoryItem {
+                copy_count: 0,
+                ..HistoryItem::new(entry, None)
+            },
+        };
+        let id = item.id.clone();
         // Add new item to front
         history.push_front(item);
-        // Keep only last 10 items
-        while history.len() > self.1 {
-            history.pop_back();
+        evict(&mut history, self.1);
+
+        id
+    }
+
+    /// Applies a sensitive-content action to an entry that is already in the history.
+    pub fn protect(&self, id: &str, action: &FilterAction) {
+        let mut history = self.0.write().unwrap();
+        if let Some(item) = history.iter_mut().find(|x| x.id == id) {
+            match action {
+                FilterAction::Mask => item.masked = true,
+                FilterAction::Expire { seconds } => {
+                    item.expires_at = Some(now_millis() + seconds * 1000)
+                }
+                FilterAction::Drop => {}
+            }
+        }
+    }
+
+    /// Removes entries whose expiry time has passed. Returns whether anything was removed.
+    pub fn remove_expired(&self) -> bool {
+        let now = now_millis();
+        self.remove_where(|x| !x.pinned && x.expires_at.is_some_and(|expires_at| expires_at <= now))
+    }
+
+    /// Removes every entry matching `predicate`. Returns whether anything was removed.
+    pub fn remove_where(&self, predicate: impl Fn(&HistoryItem) -> bool) -> bool {
+        let mut history = self.0.write().unwrap();
+        let len = history.len();
+        history.retain(|x| !predicate(x));
+
+        history.len() != len
+    }
+
+    /// Removes the entries `options` does not keep. Returns whether anything was removed.
+    pub fn clear(&self, options: &ClearOptions) -> bool {
+        let now = now_millis();
+        let mut history = self.0.write().unwrap();
+        let len = history.len();
+        let mut index = 0;
+        history.retain(|x| {
+            let recent = options.keep_last.is_some_and(|keep_last| index < keep_last);
+            index += 1;
+            let old_enough = options
+                .older_than_seconds
+                .is_none_or(|seconds| now.saturating_sub(x.last_copied_at) > seconds * 1000);
+
+            (options.keep_pinned && x.pinned) || recent || !old_enough
+        });
+
+        history.len() != len
+    }
+
+    /// Adds older entries after the current ones, skipping content that is already present.
+    pub fn append_items(&self, items: Vec<HistoryItem>) {
+        let mut history = self.0.write().unwrap();
+        for item in items {
+            if !history.iter().any(|x| x.entry == item.entry) {
+                history.push_back(item);
+            }
+        }
+        evict(&mut history, self.1);
+    }
+
+    /// Pins or unpins an entry. Returns the new state, or None if there is no such entry.
+    pub fn toggle_pin(&self, id: &str) -> Option<bool> {
+        let mut history = self.0.write().unwrap();
+        let item = history.iter_mut().find(|x| x.id == id)?;
+        item.pinned = !item.pinned;
+
+        Some(item.pinned)
+    }
+
+    /// Counts a paste of an entry. Returns false if there is no such entry.
+    pub fn record_paste(&self, id: &str) -> bool {
+        let mut history = self.0.write().unwrap();
+        match history.iter_mut().find(|x| x.id == id) {
+            Some(item) => {
+                item.paste_count += 1;
+                item.last_pasted_at = Some(now_millis());
+                true
+            }
+            None => false,
         }
     }
 
-    pub fn get_items(&self) -> Vec<String> {
+    /// Replaces the whole history, e.g. when a change is undone.
+    pub fn set_items(&self, items: Vec<HistoryItem>) {
+        let mut history = self.0.write().unwrap();
+        *history = items.into_iter().collect();
+        evict(&mut history, self.1);
+    }
+
+    pub fn get_items(&self) -> Vec<HistoryItem> {
         self.0.read().unwrap().iter().cloned().collect()
     }
 
+    pub fn get_item(&self, id: &str) -> Option<HistoryItem> {
+        self.0.read().unwrap().iter().find(|x| x.id == id).cloned()
+    }
+
     // Add remove_item method
-    pub fn remove_item(&self, item: String) {
+    pub fn remove_item(&self, id: &str) {
         let mut history = self.0.write().unwrap();
-        history.retain(|x| x != &item);
+        history.retain(|x| x.id != id);
     }
 
     pub fn change_limit(&mut self, limit: usize) {
         let old_itens = self.get_items();
 
-        self.0 = RwLock::new(VecDeque::with_capacity(limit));
-        self.1 = limit;
+        *self = ClipboardHistory::with_items(limit, old_itens);
+    }
+}
 
-        let mut i = 0;
-        for item in old_itens {
-            if (i + 1) > limit {
-                break;
+/// Drops the oldest unpinned entries until the history fits in `limit`. Pinned
+/// entries always stay, even if there are more of them than `limit`.
+fn evict(history: &mut VecDeque<HistoryItem>, limit: usize) {
+    while history.len() > limit {
+        match history.iter().rposition(|x| !x.pinned) {
+            Some(index) => {
+                history.remove(index);
             }
-
-            self.add_item(item);
-
-            i += 1;
+            None => break,
         }
     }
 }
@@ -69,14 +312,40 @@ impl Handler {
 impl ClipboardHandler for Handler {
     fn on_clipboard_change(&mut self) -> CallbackResult {
         let app = self.app.read().unwrap();
-        let clipboard = app.clipboard();
+        let filter_config = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().sensitive_filter.clone();
         let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
 
-        if let Ok(text) = clipboard.read_text() {
-            history.add_item(text.to_string());
-            drop(history);
+        if let Some(entry) = ClipboardEntry::from_clipboard(&app) {
+            // Sensitive content is checked before it ever reaches the history
+            let filter_match = filter::check(&filter_config, &entry, clipboard_has_secret_hint());
+            if let Some(filter_match) = &filter_match {
+                if filter_match.action == FilterAction::Drop {
+                    drop(history);
+                    // Let the UI tell the user something was copied but not recorded
+                    if let Err(e) = app.emit_to("main", "clipboard-suppressed", filter_match.rule.clone()) {
+                        eprintln!("Error emitting clipboard-suppressed: {}", e);
+                    }
+                    return CallbackResult::Next;
+                }
+            }
+
+            let restored = app.state::<Arc<RwLock<RestoredEntry>>>().inner().write().unwrap().take_if(&entry);
+            let id = history.add_item(entry, clipboard_owner_class(), !restored);
+            // Pasting from the tray or the queue must not queue the pasted entry again
+            if !restored {
+                app.state::<Arc<RwLock<PasteQueue>>>().inner().write().unwrap().push(&id);
+            }
+            if let Some(filter_match) = filter_match {
+                history.protect(&id, &filter_match.action);
+                if let FilterAction::Expire { seconds } = filter_match.action {
+                    schedule_expiry(app.clone(), seconds);
+                }
+            }
 
-            use crate::clipboard_manager::tray::setup_tray_menu;
+            if let Err(e) = save_history_to_file(&app, &history) {
+                eprintln!("Failed to save clipboard history: {}", e);
+            }
+            drop(history);
 
             setup_tray_menu(&app, Some(true));
 
@@ -95,3 +364,171 @@ impl ClipboardHandler for Handler {
         CallbackResult::Next
     }
 }
+
+/// Prunes expired entries once `seconds` have passed, then refreshes the tray and the UI.
+fn schedule_expiry(app: AppHandle, seconds: u64) {
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(seconds));
+        prune_history(&app);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> ClipboardEntry {
+        ClipboardEntry::from(text.to_string())
+    }
+
+    fn masked(content: &str) -> HistoryItem {
+        HistoryItem {
+            masked: true,
+            ..text(content).into()
+        }
+    }
+
+    fn texts(history: &ClipboardHistory) -> Vec<String> {
+        history.get_items().iter().map(|item| item.entry.preview()).collect()
+    }
+
+    #[test]
+    fn adds_new_entries_to_the_front() {
+        let history = ClipboardHistory::with_items(10, vec![]);
+        history.add_item(text("a"), None, true);
+        history.add_item(text("b"), Some("firefox".into()), true);
+
+        assert_eq!(texts(&history), vec!["b", "a"]);
+        assert_eq!(history.get_items()[0].source.as_deref(), Some("firefox"));
+    }
+
+    #[test]
+    fn copying_again_moves_the_entry_up_and_keeps_its_id() {
+        let history = ClipboardHistory::with_items(10, vec![]);
+        let id = history.add_item(text("a"), None, true);
+        history.add_item(text("b"), None, true);
+
+        assert_eq!(history.add_item(text("a"), None, true), id);
+        assert_eq!(texts(&history), vec!["a", "b"]);
+        assert_eq!(history.get_item(&id).unwrap().copy_count, 2);
+
+        // A paste made by clipbored is not a copy
+        history.add_item(text("b"), None, false);
+        assert_eq!(texts(&history), vec!["b", "a"]);
+        assert_eq!(history.get_items()[0].copy_count, 1);
+        let pasted = history.add_item(text("bookmark"), None, false);
+        assert_eq!(history.get_item(&pasted).unwrap().copy_count, 0);
+    }
+
+    #[test]
+    fn evicts_the_oldest_unpinned_entries() {
+        let history = ClipboardHistory::with_items(3, vec![]);
+        let oldest = history.add_item(text("oldest"), None, true);
+        history.toggle_pin(&oldest);
+        for content in ["b", "c", "d"] {
+            history.add_item(text(content), None, true);
+        }
+
+        assert_eq!(texts(&history), vec!["d", "c", "oldest"]);
+    }
+
+    #[test]
+    fn pinned_entries_stay_even_past_the_limit() {
+        let pinned = |content| HistoryItem { pinned: true, ..text(content).into() };
+        let items = vec![pinned("a"), pinned("b"), text("c").into(), pinned("d")];
+        let mut history = ClipboardHistory::with_items(2, items);
+
+        assert_eq!(texts(&history), vec!["a", "b", "d"]);
+
+        history.change_limit(1);
+        history.add_item(text("e"), None, true);
+        assert_eq!(texts(&history), vec!["a", "b", "d"]);
+        assert_eq!(history.toggle_pin("missing"), None);
+    }
+
+    /// Newest first: "new" was copied just now, "pinned" an hour ago and "old" a day ago.
+    fn history_to_clear() -> ClipboardHistory {
+        let copied = |content, seconds_ago: u64| HistoryItem {
+            last_copied_at: now_millis() - seconds_ago * 1000,
+            ..text(content).into()
+        };
+        let items = vec![
+            copied("new", 0),
+            HistoryItem { pinned: true, ..copied("pinned", 3600) },
+            copied("old", 86400),
+        ];
+
+        ClipboardHistory::with_items(10, items)
+    }
+
+    #[test]
+    fn clearing_keeps_pinned_entries_by_default() {
+        let history = history_to_clear();
+
+        assert!(history.clear(&ClearOptions::default()));
+        assert_eq!(texts(&history), vec!["pinned"]);
+        assert!(!history.clear(&ClearOptions::default()));
+
+        assert!(history.clear(&ClearOptions { keep_pinned: false, ..Default::default() }));
+        assert!(history.get_items().is_empty());
+    }
+
+    #[test]
+    fn clearing_keeps_the_last_entries() {
+        let history = history_to_clear();
+
+        history.clear(&ClearOptions { keep_pinned: false, keep_last: Some(2), older_than_seconds: None });
+
+        assert_eq!(texts(&history), vec!["new", "pinned"]);
+    }
+
+    #[test]
+    fn clearing_only_removes_entries_older_than_asked() {
+        let history = history_to_clear();
+
+        history.clear(&ClearOptions { keep_pinned: false, keep_last: None, older_than_seconds: Some(60) });
+        assert_eq!(texts(&history), vec!["new"]);
+
+        let history = history_to_clear();
+        history.clear(&ClearOptions { keep_pinned: true, keep_last: Some(1), older_than_seconds: Some(7200) });
+        assert_eq!(texts(&history), vec!["new", "pinned"]);
+    }
+
+    #[test]
+    fn clear_options_default_missing_fields() {
+        let options: ClearOptions = serde_json::from_str(r#"{ "keepLast": 3 }"#).unwrap();
+
+        assert!(options.keep_pinned);
+        assert_eq!((options.keep_last, options.older_than_seconds), (Some(3), None));
+    }
+
+    #[test]
+    fn masked_content_never_reaches_the_ui() {
+        let item = masked("hunter2");
+
+        let redacted = item.redacted();
+
+        assert_eq!(redacted.entry, text(MASKED_PLACEHOLDER));
+        assert_eq!((redacted.id, redacted.masked), (item.id, true));
+        assert_eq!(HistoryItem::from(text("hello")).redacted().entry, text("hello"));
+    }
+
+    #[test]
+    fn masked_entries_are_saved_only_when_encrypted() {
+        let expiring = HistoryItem {
+            expires_at: Some(1),
+            ..text("one-time code").into()
+        };
+        let items = vec![HistoryItem::from(text("hello")), masked("hunter2"), expiring];
+
+        let saved = |encrypted| -> Vec<String> {
+            items_to_save(&items, encrypted)
+                .iter()
+                .map(|item| item.entry.preview())
+                .collect()
+        };
+
+        assert_eq!(saved(false), vec!["hello"]);
+        assert_eq!(saved(true), vec!["hello", "hunter2"]);
+    }
+}
@@ -1,3 +1,15 @@
+pub mod bookmark_io;
+pub mod entry;
+pub mod expansion;
+pub mod expiry;
+pub mod filter;
+pub mod frecency;
 pub mod handlers;
 pub mod history;
+pub mod queue;
+pub mod search;
+pub mod snippet_dir;
+pub mod source;
+pub mod template;
+pub mod transform;
 pub mod tray;
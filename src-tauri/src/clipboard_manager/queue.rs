@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Which queued copy "paste next" takes first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum QueueOrder {
+    /// Oldest copy first, e.g. to fill a form in the order the values were copied
+    #[default]
+    Fifo,
+    /// Latest copy first
+    Lifo,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PasteQueueState {
+    pub active: bool,
+    pub length: usize,
+}
+
+/// Sequential paste mode: while active, every copy is queued (by history id) and
+/// "paste next" pastes the queued copies one by one.
+#[derive(Default)]
+pub struct PasteQueue {
+    active: bool,
+    ids: VecDeque<String>,
+}
+
+impl PasteQueue {
+    pub fn state(&self) -> PasteQueueState {
+        PasteQueueState {
+            active: self.active,
+            length: self.ids.len(),
+        }
+    }
+
+    /// Turns the mode on or off. Either way the queue starts empty.
+    pub fn toggle(&mut self) -> bool {
+        self.active = !self.active;
+        self.ids.clear();
+
+        self.active
+    }
+
+    /// Queues a copy. Called by the clipboard listener after the entry was added to the history.
+    pub fn push(&mut self, id: &str) {
+        if !self.active {
+            return;
+        }
+
+        // Copying the same thing twice queues it once, at its latest position
+        self.ids.retain(|x| x != id);
+        self.ids.push_back(id.to_string());
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.iter().any(|x| x == id)
+    }
+
+    pub fn pop(&mut self, order: QueueOrder) -> Option<String> {
+        match order {
+            QueueOrder::Fifo => self.ids.pop_front(),
+            QueueOrder::Lifo => self.ids.pop_back(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(ids: &[&str]) -> PasteQueue {
+        let mut queue = PasteQueue::default();
+        queue.toggle();
+        for id in ids {
+            queue.push(id);
+        }
+        queue
+    }
+
+    fn drain(queue: &mut PasteQueue, order: QueueOrder) -> Vec<String> {
+        std::iter::from_fn(|| queue.pop(order)).collect()
+    }
+
+    #[test]
+    fn copies_are_only_queued_while_active() {
+        let mut queue = PasteQueue::default();
+        queue.push("a");
+        assert_eq!(queue.state().length, 0);
+
+        assert!(queue.toggle());
+        queue.push("a");
+        assert!(queue.contains("a"));
+        assert_eq!(queue.state().length, 1);
+    }
+
+    #[test]
+    fn toggling_empties_the_queue() {
+        let mut queue = queue(&["a", "b"]);
+
+        assert!(!queue.toggle());
+        assert_eq!(queue.state().length, 0);
+        assert!(queue.toggle());
+        assert!(!queue.contains("a"));
+    }
+
+    #[test]
+    fn pops_in_the_configured_order() {
+        assert_eq!(drain(&mut queue(&["a", "b", "c"]), QueueOrder::Fifo), vec!["a", "b", "c"]);
+        assert_eq!(drain(&mut queue(&["a", "b", "c"]), QueueOrder::Lifo), vec!["c", "b", "a"]);
+        assert_eq!(queue(&[]).pop(QueueOrder::Fifo), None);
+    }
+
+    #[test]
+    fn copying_again_moves_the_entry_to_its_latest_position() {
+        let mut queue = queue(&["a", "b", "a"]);
+
+        assert_eq!(queue.state().length, 2);
+        assert_eq!(drain(&mut queue, QueueOrder::Fifo), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn popped_entries_are_no_longer_queued() {
+        let mut queue = queue(&["a", "b"]);
+
+        assert_eq!(queue.pop(QueueOrder::Fifo).as_deref(), Some("a"));
+        assert!(!queue.contains("a"));
+        assert!(queue.contains("b"));
+    }
+}
@@ -0,0 +1,306 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+use super::history::{HistoryItem, MASKED_PLACEHOLDER};
+use crate::commands::Bookmark;
+
+/// Only this many leading characters of each item are matched, so huge clipboard
+/// entries don't slow down the picker.
+const MAX_SEARCH_CHARS: usize = 10_000;
+
+const DEFAULT_LIMIT: usize = 50;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchFilters {
+    /// Entry kinds to include ("text", "html", "image", "fileList"). Empty means all.
+    pub kinds: Vec<String>,
+    /// Only history entries last copied at or after this time (ms since epoch).
+    /// Bookmarks have no copy time, so any date filter leaves them out.
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub bookmarked_only: bool,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchSource {
+    History,
+    Bookmark,
+}
+
+/// The part of a bookmark the query matched. History entries only have content.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchedField {
+    Content,
+    Title,
+    Description,
+    Tag,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    /// Id of the history entry or bookmark
+    pub id: String,
+    pub source: SearchSource,
+    /// The text that matched, from `field`
+    pub text: String,
+    pub field: MatchedField,
+    pub score: i64,
+    /// Matched character ranges in `text`, as `[start, end)` pairs
+    pub highlights: Vec<(usize, usize)>,
+    pub bookmarked: bool,
+}
+
+/// Ranks history entries and bookmarks against `query`. An empty query keeps the
+/// bookmarks first and the history in recency order.
+pub fn search(
+    query: &str,
+    filters: &SearchFilters,
+    history: &[HistoryItem],
+    bookmarks: &[Bookmark],
+) -> Vec<SearchResult> {
+    let matcher = SkimMatcherV2::default().smart_case();
+    let query = query.trim();
+    let has_date_filter = filters.from.is_some() || filters.to.is_some();
+    let kind_allowed =
+        |kind: &str| filters.kinds.is_empty() || filters.kinds.iter().any(|k| k == kind);
+
+    let mut results = Vec::new();
+
+    if !has_date_filter && kind_allowed("text") {
+        for bookmark in bookmarks {
+            if let Some(result) = match_bookmark(&matcher, query, bookmark) {
+                results.push(SearchResult {
+                    id: bookmark.id.clone(),
+                    source: SearchSource::Bookmark,
+                    bookmarked: true,
+                    ..result
+                });
+            }
+        }
+    }
+
+    for item in history {
+        if !kind_allowed(item.entry.kind())
+            || filters.from.is_some_and(|from| item.last_copied_at < from)
+            || filters.to.is_some_and(|to| item.last_copied_at > to)
+        {
+            continue;
+        }
+
+        let bookmarked = bookmarks
+            .iter()
+            .any(|b| b.history_id.as_deref() == Some(item.id.as_str()));
+        if filters.bookmarked_only && !bookmarked {
+            continue;
+        }
+
+        // Masked entries are listed but their content is never matched or returned
+        let text = if item.masked {
+            MASKED_PLACEHOLDER.to_string()
+        } else {
+            item.entry.text_content().unwrap_or_else(|| item.entry.preview())
+        };
+        if let Some(result) = match_text(&matcher, query, &text) {
+            results.push(SearchResult {
+                id: item.id.clone(),
+                source: SearchSource::History,
+                bookmarked,
+                ..result
+            });
+        }
+    }
+
+    // Stable sort: equal scores keep bookmarks first and history in recency order
+    results.sort_by_key(|result| Reverse(result.score));
+    results.truncate(filters.limit.unwrap_or(DEFAULT_LIMIT));
+
+    results
+}
+
+fn match_text(matcher: &SkimMatcherV2, query: &str, text: &str) -> Option<SearchResult> {
+    let text: String = text.chars().take(MAX_SEARCH_CHARS).collect();
+
+    let (score, indices) = if query.is_empty() {
+        (0, vec![])
+    } else {
+        matcher.fuzzy_indices(&text, query)?
+    };
+
+    Some(SearchResult {
+        id: String::new(),
+        source: SearchSource::History,
+        text,
+        field: MatchedField::Content,
+        score,
+        highlights: indices_to_ranges(&indices),
+        bookmarked: false,
+    })
+}
+
+/// Best match among the content, title, description and tags of `bookmark`. On a
+/// tie the content wins, so an empty query shows the content.
+fn match_bookmark(matcher: &SkimMatcherV2, query: &str, bookmark: &Bookmark) -> Option<SearchResult> {
+    let fields = [
+        (MatchedField::Content, Some(&bookmark.content)),
+        (MatchedField::Title, bookmark.title.as_ref()),
+        (MatchedField::Description, bookmark.description.as_ref()),
+    ]
+    .into_iter()
+    .filter_map(|(field, text)| Some((field, text?)))
+    .chain(bookmark.tags.iter().map(|tag| (MatchedField::Tag, tag)));
+
+    let mut best: Option<SearchResult> = None;
+    for (field, text) in fields {
+        if let Some(result) = match_text(matcher, query, text) {
+            if best.as_ref().is_none_or(|best| result.score > best.score) {
+                best = Some(SearchResult { field, ..result });
+            }
+        }
+    }
+
+    best
+}
+
+/// Merges consecutive matched character indices into ranges.
+fn indices_to_ranges(indices: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for &index in indices {
+        match ranges.last_mut() {
+            Some((_, end)) if *end == index => *end += 1,
+            _ => ranges.push((index, index + 1)),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard_manager::entry::ClipboardEntry;
+
+    fn item(text: &str, last_copied_at: u64) -> HistoryItem {
+        HistoryItem {
+            last_copied_at,
+            ..ClipboardEntry::from(text.to_string()).into()
+        }
+    }
+
+    fn texts(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|result| result.text.as_str()).collect()
+    }
+
+    #[test]
+    fn merges_consecutive_indices_into_ranges() {
+        assert_eq!(indices_to_ranges(&[]), vec![]);
+        assert_eq!(indices_to_ranges(&[0, 1, 2, 5, 7, 8]), vec![(0, 3), (5, 6), (7, 9)]);
+    }
+
+    #[test]
+    fn highlights_the_matched_characters() {
+        let history = vec![item("the quick brown fox", 1)];
+
+        let results = search("quick", &SearchFilters::default(), &history, &[]);
+        assert_eq!(results[0].highlights, vec![(4, 9)]);
+
+        let results = search("qbf", &SearchFilters::default(), &history, &[]);
+        assert_eq!(results[0].highlights, vec![(4, 5), (10, 11), (16, 17)]);
+    }
+
+    #[test]
+    fn highlights_count_characters_not_bytes() {
+        let history = vec![item("café crème", 1)];
+
+        let results = search("crème", &SearchFilters::default(), &history, &[]);
+
+        assert_eq!(results[0].highlights, vec![(5, 10)]);
+    }
+
+    #[test]
+    fn empty_query_lists_bookmarks_first_then_history_in_order() {
+        let history = vec![item("newest", 2), item("older", 1)];
+        let bookmarks = vec![Bookmark::new("bookmark".into())];
+
+        let results = search("  ", &SearchFilters::default(), &history, &bookmarks);
+
+        assert_eq!(texts(&results), vec!["bookmark", "newest", "older"]);
+        assert!(results.iter().all(|result| result.highlights.is_empty()));
+    }
+
+    #[test]
+    fn leaves_out_what_does_not_match() {
+        let history = vec![item("apple pie", 1), item("banana", 1)];
+
+        let results = search("pie", &SearchFilters::default(), &history, &[]);
+
+        assert_eq!(texts(&results), vec!["apple pie"]);
+    }
+
+    #[test]
+    fn matches_bookmark_titles_descriptions_and_tags() {
+        let bookmark = Bookmark {
+            title: Some("Email signature".into()),
+            description: Some("Used for work mail".into()),
+            tags: vec!["greeting".into(), "office".into()],
+            ..Bookmark::new("Best regards, Ann".into())
+        };
+        let bookmarks = vec![bookmark];
+
+        let matched = |query| {
+            let result = search(query, &SearchFilters::default(), &[], &bookmarks).remove(0);
+            (result.field, result.text, result.highlights)
+        };
+
+        assert_eq!(matched("regards"), (MatchedField::Content, "Best regards, Ann".into(), vec![(5, 12)]));
+        assert_eq!(matched("signature"), (MatchedField::Title, "Email signature".into(), vec![(6, 15)]));
+        assert_eq!(matched("work mail"), (MatchedField::Description, "Used for work mail".into(), vec![(9, 18)]));
+        assert_eq!(matched("office"), (MatchedField::Tag, "office".into(), vec![(0, 6)]));
+        assert_eq!(matched("").0, MatchedField::Content);
+        assert!(search("invoice", &SearchFilters::default(), &[], &bookmarks).is_empty());
+    }
+
+    #[test]
+    fn never_matches_masked_content() {
+        let history = vec![HistoryItem { masked: true, ..item("hunter2", 1) }];
+
+        assert!(search("hunter", &SearchFilters::default(), &history, &[]).is_empty());
+        let results = search("", &SearchFilters::default(), &history, &[]);
+        assert_eq!(texts(&results), vec!["••••••••"]);
+    }
+
+    #[test]
+    fn filters_by_date_kind_bookmark_and_limit() {
+        let image = HistoryItem {
+            last_copied_at: 100,
+            ..ClipboardEntry::Image { hash: "abc".into(), width: 2, height: 2 }.into()
+        };
+        let history = vec![item("recent", 300), item("old", 100), image];
+        let bookmark = Bookmark {
+            history_id: Some(history[1].id.clone()),
+            ..Bookmark::new("bookmark".into())
+        };
+        let bookmarks = vec![bookmark];
+
+        let filters = SearchFilters { from: Some(200), ..Default::default() };
+        assert_eq!(texts(&search("", &filters, &history, &bookmarks)), vec!["recent"]);
+
+        let filters = SearchFilters { kinds: vec!["image".into()], ..Default::default() };
+        assert_eq!(texts(&search("", &filters, &history, &bookmarks)), vec!["[Image 2x2]"]);
+
+        let filters = SearchFilters { bookmarked_only: true, ..Default::default() };
+        let results = search("", &filters, &history, &bookmarks);
+        assert_eq!(texts(&results), vec!["bookmark", "old"]);
+        assert!(results.iter().all(|result| result.bookmarked));
+
+        let filters = SearchFilters { limit: Some(2), ..Default::default() };
+        assert_eq!(search("", &filters, &history, &bookmarks).len(), 2);
+    }
+}
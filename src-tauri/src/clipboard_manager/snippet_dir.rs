@@ -0,0 +1,400 @@
+//! Bookmarks mirrored from a directory of snippet files, e.g. a team's shared git
+//! checkout: every file is a bookmark (titled after the file name) and every
+//! subdirectory a folder. They are kept apart from bookmarks.json and merged with
+//! the personal bookmarks wherever bookmarks are listed or pasted. Their paste
+//! counts are kept in snippet-stats.json, so the shared files are never touched.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Emitter, Manager};
+
+use super::history::now_millis;
+use super::tray::setup_tray_menu;
+use crate::commands::{AppConfig, Bookmark, BookmarkFolder};
+
+/// How often the directory is checked for changes. Each check lists the whole tree,
+/// so this is kept well above what a `git pull` needs to show up.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SnippetDirMode {
+    #[default]
+    ReadOnly,
+    /// Editing a snippet writes its file and removing it deletes the file
+    WriteThrough,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SnippetDirConfig {
+    /// None disables the snippet directory
+    pub path: Option<String>,
+    pub mode: SnippetDirMode,
+}
+
+/// A file or directory found under the snippet directory, to tell when it changed.
+#[derive(Debug, Clone, PartialEq)]
+struct DirEntry {
+    relative: PathBuf,
+    is_dir: bool,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Paste count of a snippet, by snippet id in snippet-stats.json.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetStats {
+    pub paste_count: u32,
+    pub last_pasted_at: Option<u64>,
+}
+
+/// The bookmarks and folders currently read from the snippet directory.
+#[derive(Default)]
+pub struct SnippetDir {
+    root: Option<PathBuf>,
+    entries: Vec<DirEntry>,
+    bookmarks: Vec<Bookmark>,
+    folders: Vec<BookmarkFolder>,
+    stats: HashMap<String, SnippetStats>,
+}
+
+impl SnippetDir {
+    /// Nothing read yet, only the paste counts saved by earlier runs.
+    pub fn load(app: &AppHandle) -> Self {
+        let stats = fs::read_to_string(stats_path(app))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        SnippetDir {
+            stats,
+            ..Default::default()
+        }
+    }
+}
+
+/// Personal bookmarks followed by the snippet directory's.
+pub fn merged_bookmarks(app: &AppHandle) -> Vec<Bookmark> {
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().read().unwrap().clone();
+    let snippet_dir = app.state::<Arc<RwLock<SnippetDir>>>();
+    bookmarks.extend(snippet_dir.inner().read().unwrap().bookmarks.iter().cloned());
+
+    bookmarks
+}
+
+/// Personal folders followed by the snippet directory's.
+pub fn merged_folders(app: &AppHandle) -> Vec<BookmarkFolder> {
+    let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().read().unwrap().clone();
+    let snippet_dir = app.state::<Arc<RwLock<SnippetDir>>>();
+    folders.extend(snippet_dir.inner().read().unwrap().folders.iter().cloned());
+
+    folders
+}
+
+/// Reads the configured directory again if it (or the setting) changed. Returns
+/// whether the snippets changed.
+pub fn reload(app: &AppHandle) -> bool {
+    let root = app
+        .state::<Arc<RwLock<AppConfig>>>()
+        .inner()
+        .read()
+        .unwrap()
+        .snippet_dir
+        .path
+        .clone()
+        .map(PathBuf::from);
+    let mut entries = Vec::new();
+    let mut warnings = Vec::new();
+    if let Some(root) = &root {
+        if let Err(e) = scan(root, Path::new(""), &mut entries, &mut warnings) {
+            eprintln!("Failed to read snippet directory {}: {}", root.display(), e);
+        }
+    }
+
+    let state = app.state::<Arc<RwLock<SnippetDir>>>();
+    let mut snippet_dir = state.inner().write().unwrap();
+    if snippet_dir.root == root && snippet_dir.entries == entries {
+        return false;
+    }
+
+    let (bookmarks, folders) = match &root {
+        Some(root) => read_snippets(root, &entries, &snippet_dir.stats, &mut warnings),
+        None => (vec![], vec![]),
+    };
+    // Only reported when something changed, not on every check
+    for warning in warnings {
+        eprintln!("Snippet directory: {}", warning);
+    }
+    let stats = std::mem::take(&mut snippet_dir.stats);
+    *snippet_dir = SnippetDir {
+        root,
+        entries,
+        bookmarks,
+        folders,
+        stats,
+    };
+
+    true
+}
+
+/// Checks the directory every POLL_INTERVAL and refreshes the UI and the tray when
+/// a snippet was added, changed or removed (e.g. by a `git pull`).
+pub fn start_watching(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(POLL_INTERVAL);
+        if reload(&app) {
+            let _ = app.emit_to("main", "bookmarks-updated", ());
+            setup_tray_menu(&app, Some(true));
+        }
+    });
+}
+
+/// Writes the file behind a snippet, or deletes it when `content` is None. Returns
+/// None if `id` is not a snippet from the directory.
+pub fn write_through(app: &AppHandle, id: &str, content: Option<&str>) -> Option<Result<(), String>> {
+    let mode = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().snippet_dir.mode;
+    let state = app.state::<Arc<RwLock<SnippetDir>>>();
+    let snippet_dir = state.inner().read().unwrap();
+    let file = snippet_dir.bookmarks.iter().find(|b| b.id == id)?.file.clone()?;
+    let path = snippet_dir.root.as_ref()?.join(file);
+    drop(snippet_dir);
+
+    if mode == SnippetDirMode::ReadOnly {
+        return Some(Err("Snippets from the snippet directory are read-only".into()));
+    }
+    let result = match content {
+        Some(content) => fs::write(&path, content),
+        None => fs::remove_file(&path),
+    };
+    if let Err(e) = result {
+        return Some(Err(format!("Failed to write {}: {}", path.display(), e)));
+    }
+
+    if reload(app) {
+        let _ = app.emit_to("main", "bookmarks-updated", ());
+        let app = app.clone();
+        std::thread::spawn(move || {
+            setup_tray_menu(app.app_handle(), Some(true));
+        });
+    }
+
+    Some(Ok(()))
+}
+
+/// Counts a paste of a snippet in snippet-stats.json. Returns None if `id` is not a
+/// snippet from the directory.
+pub fn record_paste(app: &AppHandle, id: &str) -> Option<Result<(), String>> {
+    let state = app.state::<Arc<RwLock<SnippetDir>>>();
+    let mut snippet_dir = state.inner().write().unwrap();
+    let index = snippet_dir.bookmarks.iter().position(|b| b.id == id)?;
+
+    let mut stats = snippet_dir.stats.clone();
+    let entry = stats.entry(id.to_string()).or_default();
+    entry.paste_count += 1;
+    entry.last_pasted_at = Some(now_millis());
+    let pasted = *entry;
+
+    // Saved first, so the counts in memory never run ahead of the file
+    if let Err(e) = save_stats(app, &stats) {
+        return Some(Err(format!("Failed to save snippet paste counts: {}", e)));
+    }
+
+    snippet_dir.stats = stats;
+    let bookmark = &mut snippet_dir.bookmarks[index];
+    bookmark.paste_count = pasted.paste_count;
+    bookmark.last_pasted_at = pasted.last_pasted_at;
+
+    Some(Ok(()))
+}
+
+fn stats_path(app: &AppHandle) -> PathBuf {
+    app.path().app_local_data_dir().unwrap().join("snippet-stats.json")
+}
+
+fn save_stats(app: &AppHandle, stats: &HashMap<String, SnippetStats>) -> Result<(), Box<dyn std::error::Error>> {
+    let serialized_stats = serde_json::to_string(stats)?;
+    fs::write(stats_path(app), serialized_stats)?;
+
+    Ok(())
+}
+
+/// Lists everything under `dir` except hidden files (such as `.git`), sorted by path.
+/// Entries that can't be read are skipped with a warning; only failing to list
+/// `dir` itself is an error.
+fn scan(root: &Path, dir: &Path, entries: &mut Vec<DirEntry>, warnings: &mut Vec<String>) -> std::io::Result<()> {
+    let mut children: Vec<_> = fs::read_dir(root.join(dir))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .collect();
+    children.sort_by_key(|entry| entry.file_name());
+
+    for child in children {
+        let relative = dir.join(child.file_name());
+        let metadata = match child.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warnings.push(format!("skipped {}: {}", relative.display(), e));
+                continue;
+            }
+        };
+        entries.push(DirEntry {
+            relative: relative.clone(),
+            is_dir: metadata.is_dir(),
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        });
+        if metadata.is_dir() {
+            if let Err(e) = scan(root, &relative, entries, warnings) {
+                warnings.push(format!("skipped {}: {}", relative.display(), e));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_snippets(
+    root: &Path,
+    entries: &[DirEntry],
+    stats: &HashMap<String, SnippetStats>,
+    warnings: &mut Vec<String>,
+) -> (Vec<Bookmark>, Vec<BookmarkFolder>) {
+    let mut bookmarks = Vec::new();
+    let mut folders = Vec::new();
+    let parent_id = |relative: &Path| {
+        relative
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(snippet_id)
+    };
+
+    for entry in entries {
+        let name = entry.relative.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if entry.is_dir {
+            folders.push(BookmarkFolder {
+                id: snippet_id(&entry.relative),
+                name,
+                parent_id: parent_id(&entry.relative),
+                path: Some(entry.relative.to_string_lossy().to_string()),
+            });
+            continue;
+        }
+
+        let content = match fs::read_to_string(root.join(&entry.relative)) {
+            Ok(content) => content,
+            // Binary files are not snippets
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+            Err(e) => {
+                warnings.push(format!("skipped {}: {}", entry.relative.display(), e));
+                continue;
+            }
+        };
+        let title = entry.relative.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let id = snippet_id(&entry.relative);
+        let stats = stats.get(&id).copied().unwrap_or_default();
+        bookmarks.push(Bookmark {
+            id,
+            title: Some(title),
+            paste_count: stats.paste_count,
+            last_pasted_at: stats.last_pasted_at,
+            folder_id: parent_id(&entry.relative),
+            file: Some(entry.relative.to_string_lossy().to_string()),
+            ..Bookmark::new(content)
+        });
+    }
+
+    (bookmarks, folders)
+}
+
+/// Ids follow the path, so a snippet keeps its id (and tray item) when it is reloaded.
+fn snippet_id(relative: &Path) -> String {
+    let hash = format!("{:x}", Sha256::digest(relative.to_string_lossy().as_bytes()));
+    format!("dir-{}", &hash[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard_manager::history::new_id;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("clipbored-test-{}", new_id()));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn read(root: &Path, stats: &HashMap<String, SnippetStats>) -> (Vec<Bookmark>, Vec<BookmarkFolder>, Vec<String>) {
+        let mut entries = Vec::new();
+        let mut warnings = Vec::new();
+        scan(root, Path::new(""), &mut entries, &mut warnings).unwrap();
+        let (bookmarks, folders) = read_snippets(root, &entries, stats, &mut warnings);
+
+        (bookmarks, folders, warnings)
+    }
+
+    #[test]
+    fn files_become_bookmarks_and_directories_folders() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.0.join("mail/replies")).unwrap();
+        fs::create_dir_all(dir.0.join(".git")).unwrap();
+        fs::write(dir.0.join("mail/signature.txt"), "Best regards").unwrap();
+        fs::write(dir.0.join("mail/replies/thanks.md"), "Thanks!").unwrap();
+        fs::write(dir.0.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        fs::write(dir.0.join("logo.png"), [0x89, b'P', b'N', b'G', 0xff, 0xfe]).unwrap();
+        let stats = HashMap::from([(
+            snippet_id(Path::new("mail/signature.txt")),
+            SnippetStats { paste_count: 3, last_pasted_at: Some(42) },
+        )]);
+
+        let (bookmarks, folders, warnings) = read(&dir.0, &stats);
+
+        let folder_paths: Vec<_> = folders.iter().map(|f| f.path.as_deref().unwrap()).collect();
+        assert_eq!(folder_paths, vec!["mail", "mail/replies"]);
+        assert_eq!(folders[1].parent_id.as_deref(), Some(folders[0].id.as_str()));
+
+        let titles: Vec<_> = bookmarks.iter().map(|b| b.label()).collect();
+        assert_eq!(titles, vec!["thanks", "signature"]);
+        assert_eq!(bookmarks[0].folder_id.as_deref(), Some(folders[1].id.as_str()));
+        assert_eq!(bookmarks[1].content, "Best regards");
+        assert_eq!((bookmarks[1].paste_count, bookmarks[1].last_pasted_at), (3, Some(42)));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn ids_follow_the_path() {
+        assert_eq!(snippet_id(Path::new("a/b.txt")), snippet_id(Path::new("a/b.txt")));
+        assert_ne!(snippet_id(Path::new("a/b.txt")), snippet_id(Path::new("a/c.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_files_are_skipped_with_a_warning() {
+        let dir = TempDir::new();
+        fs::write(dir.0.join("hello.txt"), "Hello").unwrap();
+        std::os::unix::fs::symlink(dir.0.join("missing.txt"), dir.0.join("broken.txt")).unwrap();
+
+        let (bookmarks, _, warnings) = read(&dir.0, &HashMap::new());
+
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].content, "Hello");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("skipped broken.txt"), "{}", warnings[0]);
+    }
+}
@@ -0,0 +1,158 @@
+/// Clipboard target set by KeePassXC, KDE and other password managers when they copy a secret
+#[cfg(target_os = "linux")]
+const PASSWORD_MANAGER_HINT: &str = "x-kde-passwordManagerHint";
+
+/// WM_CLASS of the window that currently owns the CLIPBOARD selection.
+///
+/// The selection owner is often an unmapped helper window, so the window tree is
+/// walked upwards until one of the ancestors has a WM_CLASS.
+#[cfg(target_os = "linux")]
+pub fn clipboard_owner_class() -> Option<String> {
+    use x11rb::properties::WmClass;
+    use x11rb::protocol::xproto::ConnectionExt;
+
+    let (conn, _) = x11rb::connect(None).ok()?;
+    let clipboard = conn.intern_atom(false, b"CLIPBOARD").ok()?.reply().ok()?.atom;
+    let mut window = conn.get_selection_owner(clipboard).ok()?.reply().ok()?.owner;
+
+    while window != x11rb::NONE {
+        if let Ok(Some(wm_class)) = WmClass::get(&conn, window).ok()?.reply() {
+            return Some(String::from_utf8_lossy(wm_class.class()).to_string());
+        }
+
+        let tree = conn.query_tree(window).ok()?.reply().ok()?;
+        if tree.parent == tree.root {
+            break;
+        }
+        window = tree.parent;
+    }
+
+    None
+}
+
+/// WM_CLASS of the window that has the focus, from the window manager's
+/// `_NET_ACTIVE_WINDOW`.
+#[cfg(target_os = "linux")]
+pub fn active_window_class() -> Option<String> {
+    use x11rb::connection::Connection;
+    use x11rb::properties::WmClass;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots.get(screen_num)?.root;
+    let active_window = conn.intern_atom(false, b"_NET_ACTIVE_WINDOW").ok()?.reply().ok()?.atom;
+    let window = conn
+        .get_property(false, root, active_window, AtomEnum::WINDOW, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()?;
+
+    let wm_class = WmClass::get(&conn, window).ok()?.reply().ok()??;
+    Some(String::from_utf8_lossy(wm_class.class()).to_string())
+}
+
+/// Whether the clipboard owner flagged the current contents as a secret
+/// (`x-kde-passwordManagerHint: secret`).
+#[cfg(target_os = "linux")]
+pub fn clipboard_has_secret_hint() -> bool {
+    let targets = read_clipboard_target("TARGETS").unwrap_or_default();
+    if !targets.iter().any(|target| target == PASSWORD_MANAGER_HINT) {
+        return false;
+    }
+
+    read_clipboard_target(PASSWORD_MANAGER_HINT)
+        .map(|values| values.iter().any(|value| value.trim() == "secret"))
+        .unwrap_or(false)
+}
+
+/// Asks the clipboard owner to convert its contents to `target` and returns the
+/// result. ATOM lists (such as TARGETS) are returned as atom names, anything else
+/// as a single string.
+#[cfg(target_os = "linux")]
+fn read_clipboard_target(target: &str) -> Option<Vec<String>> {
+    use std::time::{Duration, Instant};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, CreateWindowAux, WindowClass};
+    use x11rb::protocol::Event;
+
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots.get(screen_num)?.root;
+    let intern = |name: &str| -> Option<u32> {
+        Some(conn.intern_atom(false, name.as_bytes()).ok()?.reply().ok()?.atom)
+    };
+    let clipboard = intern("CLIPBOARD")?;
+    let target_atom = intern(target)?;
+    let property = intern("CLIPBORED_TARGET")?;
+
+    let window = conn.generate_id().ok()?;
+    conn.create_window(
+        x11rb::COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        0,
+        &CreateWindowAux::new(),
+    )
+    .ok()?;
+    conn.convert_selection(window, clipboard, target_atom, property, x11rb::CURRENT_TIME)
+        .ok()?;
+    conn.flush().ok()?;
+
+    // Don't hang the clipboard listener on an owner that never answers
+    let deadline = Instant::now() + Duration::from_millis(500);
+    let mut converted = false;
+    while Instant::now() < deadline {
+        match conn.poll_for_event() {
+            Ok(Some(Event::SelectionNotify(event))) if event.requestor == window => {
+                converted = event.property != x11rb::NONE;
+                break;
+            }
+            Ok(_) => std::thread::sleep(Duration::from_millis(5)),
+            Err(_) => break,
+        }
+    }
+
+    let reply = if converted {
+        conn.get_property(true, window, property, AtomEnum::ANY, 0, u32::MAX)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+    } else {
+        None
+    };
+    let _ = conn.destroy_window(window);
+    let _ = conn.flush();
+    let reply = reply?;
+
+    if reply.type_ == u32::from(AtomEnum::ATOM) {
+        let names = reply
+            .value32()?
+            .filter_map(|atom| conn.get_atom_name(atom).ok()?.reply().ok())
+            .map(|name| String::from_utf8_lossy(&name.name).to_string())
+            .collect();
+        Some(names)
+    } else {
+        Some(vec![String::from_utf8_lossy(&reply.value).to_string()])
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn clipboard_owner_class() -> Option<String> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn active_window_class() -> Option<String> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn clipboard_has_secret_hint() -> bool {
+    false
+}
@@ -0,0 +1,204 @@
+//! Placeholders in bookmark content, expanded when the bookmark is pasted:
+//!
+//! - `{date}` / `{date:%d/%m/%Y}`: today's date, with an optional strftime format
+//! - `{time}` / `{time:%H:%M}`: the current time
+//! - `{clipboard}`: the text currently on the clipboard
+//! - `{uuid}`: a new random UUID
+//! - `{env:NAME}`: the value of an environment variable
+//! - `{prompt:Name}`: a value the UI asks for before pasting
+//!
+//! Anything else between braces is left as it is, so code snippets paste unchanged.
+
+use chrono::Local;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::transform::Transform;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_TIME_FORMAT: &str = "%H:%M:%S";
+
+/// Sent to the UI when a bookmark needs values for its `{prompt:...}` fields.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldsRequest {
+    /// Bookmark id, to paste again once the fields are filled in
+    pub id: String,
+    pub fields: Vec<String>,
+    /// Transformation the paste was asked with, to apply once the fields are filled in
+    pub transform: Option<Transform>,
+}
+
+/// Values needed to expand a template, besides the current date and time.
+pub struct TemplateContext<'a> {
+    pub clipboard: Option<String>,
+    pub fields: &'a HashMap<String, String>,
+}
+
+/// Names of the `{prompt:...}` fields in `template`, in order and without duplicates.
+pub fn prompt_fields(template: &str) -> Vec<String> {
+    let mut fields: Vec<String> = Vec::new();
+
+    for (name, argument) in placeholders(template) {
+        if let ("prompt", Some(field)) = (name, argument) {
+            if !fields.iter().any(|x| x == field) {
+                fields.push(field.to_string());
+            }
+        }
+    }
+
+    fields
+}
+
+/// Whether `template` reads the clipboard, so callers only read it when needed.
+pub fn uses_clipboard(template: &str) -> bool {
+    placeholders(template).any(|(name, _)| name == "clipboard")
+}
+
+pub fn expand(template: &str, context: &TemplateContext) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let end = after.find('}').filter(|&end| !after[..end].contains('{'));
+        match end.and_then(|end| Some((end, expand_placeholder(&after[..end], context)?))) {
+            Some((end, value)) => {
+                expanded.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+
+    expanded
+}
+
+/// Value of one placeholder (the text between the braces), or None if it is not one we know.
+fn expand_placeholder(inner: &str, context: &TemplateContext) -> Option<String> {
+    let (name, argument) = split_placeholder(inner);
+
+    match (name, argument) {
+        ("date", format) => format_now(format.unwrap_or(DEFAULT_DATE_FORMAT)),
+        ("time", format) => format_now(format.unwrap_or(DEFAULT_TIME_FORMAT)),
+        ("clipboard", None) => Some(context.clipboard.clone().unwrap_or_default()),
+        ("uuid", None) => Some(uuid::Uuid::new_v4().to_string()),
+        ("env", Some(variable)) => Some(std::env::var(variable).unwrap_or_default()),
+        ("prompt", Some(field)) => context.fields.get(field).cloned(),
+        _ => None,
+    }
+}
+
+/// An invalid strftime format leaves the placeholder unexpanded instead of panicking.
+fn format_now(format: &str) -> Option<String> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", Local::now().format(format)).ok()?;
+
+    Some(formatted)
+}
+
+fn split_placeholder(inner: &str) -> (&str, Option<&str>) {
+    match inner.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument)),
+        None => (inner.trim(), None),
+    }
+}
+
+fn placeholders(template: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    template.split('{').skip(1).filter_map(|part| {
+        let inner = &part[..part.find('}')?];
+        Some(split_placeholder(inner))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_with(template: &str, clipboard: Option<&str>, fields: &[(&str, &str)]) -> String {
+        let fields = fields.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let context = TemplateContext {
+            clipboard: clipboard.map(String::from),
+            fields: &fields,
+        };
+
+        expand(template, &context)
+    }
+
+    #[test]
+    fn expands_clipboard_and_prompt_fields() {
+        let expanded = expand_with(
+            "Hi {prompt:Name}, see {clipboard}. Bye {prompt:Name}",
+            Some("the link"),
+            &[("Name", "Ana")],
+        );
+
+        assert_eq!(expanded, "Hi Ana, see the link. Bye Ana");
+        assert_eq!(expand_with("[{clipboard}]", None, &[]), "[]");
+    }
+
+    #[test]
+    fn expands_dates_with_a_format() {
+        let year = Local::now().format("%Y").to_string();
+
+        assert_eq!(expand_with("{date:%Y}", None, &[]), year);
+        assert_eq!(expand_with("{date}", None, &[]).len(), "2024-01-31".len());
+        assert_eq!(expand_with("{time:%H:%M}", None, &[]).len(), "12:00".len());
+    }
+
+    #[test]
+    fn expands_uuid_and_env() {
+        let uuid = expand_with("{uuid}", None, &[]);
+        assert!(uuid::Uuid::parse_str(&uuid).is_ok());
+        assert_ne!(uuid, expand_with("{uuid}", None, &[]));
+
+        let path = std::env::var("PATH").unwrap_or_default();
+        assert_eq!(expand_with("{env:PATH}", None, &[]), path);
+        assert_eq!(expand_with("[{env:CLIPBORED_UNSET_VARIABLE}]", None, &[]), "[]");
+    }
+
+    #[test]
+    fn leaves_unknown_placeholders_and_code_alone() {
+        for template in [
+            "fn main() { println!(\"{}\", x); }",
+            "{\"json\": {\"nested\": true}}",
+            "{unknown} {prompt:Missing} {uuid:extra} {",
+            "}{",
+        ] {
+            assert_eq!(expand_with(template, Some("clip"), &[]), template);
+        }
+    }
+
+    #[test]
+    fn expands_placeholders_inside_braces() {
+        assert_eq!(expand_with("{{clipboard}}", Some("x"), &[]), "{x}");
+        assert_eq!(expand_with("if (a) { {clipboard} }", Some("b()"), &[]), "if (a) { b() }");
+    }
+
+    #[test]
+    fn invalid_date_formats_are_left_unexpanded() {
+        assert_eq!(expand_with("{date:%Q}", None, &[]), "{date:%Q}");
+    }
+
+    #[test]
+    fn lists_prompt_fields_once_in_order() {
+        let fields = prompt_fields("{prompt:B} {prompt:A} {clipboard} {prompt:B} {prompt}");
+
+        assert_eq!(fields, vec!["B", "A"]);
+        assert!(prompt_fields("{date} {uuid}").is_empty());
+    }
+
+    #[test]
+    fn tells_whether_the_clipboard_is_used() {
+        assert!(uses_clipboard("Quote: { {clipboard} }"));
+        assert!(!uses_clipboard("{prompt:clipboard}"));
+        assert!(!uses_clipboard("{date} clipboard"));
+    }
+}
@@ -0,0 +1,318 @@
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// A text transformation applied when an item is pasted. The stored item is never changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Transform {
+    /// Text only, dropping HTML and other rich formats
+    Plain,
+    Trim,
+    Upper,
+    Lower,
+    Title,
+    CollapseWhitespace,
+    UrlEncode,
+    UrlDecode,
+    Base64Encode,
+    Base64Decode,
+    JsonPretty,
+    JsonMinify,
+    /// Single-quoted for POSIX shells
+    ShellEscape,
+    /// Single-quoted SQL string literal
+    SqlEscape,
+}
+
+impl Transform {
+    /// In the order they appear in the tray submenu.
+    pub const ALL: [Transform; 14] = [
+        Transform::Plain,
+        Transform::Trim,
+        Transform::Upper,
+        Transform::Lower,
+        Transform::Title,
+        Transform::CollapseWhitespace,
+        Transform::UrlEncode,
+        Transform::UrlDecode,
+        Transform::Base64Encode,
+        Transform::Base64Decode,
+        Transform::JsonPretty,
+        Transform::JsonMinify,
+        Transform::ShellEscape,
+        Transform::SqlEscape,
+    ];
+
+    /// Same name as the serialized value, used in tray menu ids.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::Plain => "plain",
+            Transform::Trim => "trim",
+            Transform::Upper => "upper",
+            Transform::Lower => "lower",
+            Transform::Title => "title",
+            Transform::CollapseWhitespace => "collapseWhitespace",
+            Transform::UrlEncode => "urlEncode",
+            Transform::UrlDecode => "urlDecode",
+            Transform::Base64Encode => "base64Encode",
+            Transform::Base64Decode => "base64Decode",
+            Transform::JsonPretty => "jsonPretty",
+            Transform::JsonMinify => "jsonMinify",
+            Transform::ShellEscape => "shellEscape",
+            Transform::SqlEscape => "sqlEscape",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Transform> {
+        Transform::ALL.into_iter().find(|transform| transform.name() == name)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Transform::Plain => "Plain text",
+            Transform::Trim => "Trim",
+            Transform::Upper => "UPPER CASE",
+            Transform::Lower => "lower case",
+            Transform::Title => "Title Case",
+            Transform::CollapseWhitespace => "Collapse whitespace",
+            Transform::UrlEncode => "URL encode",
+            Transform::UrlDecode => "URL decode",
+            Transform::Base64Encode => "Base64 encode",
+            Transform::Base64Decode => "Base64 decode",
+            Transform::JsonPretty => "JSON pretty-print",
+            Transform::JsonMinify => "JSON minify",
+            Transform::ShellEscape => "Escape for shell",
+            Transform::SqlEscape => "Escape for SQL",
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> Result<String, String> {
+        let base64 = base64::engine::general_purpose::STANDARD;
+
+        Ok(match self {
+            Transform::Plain => text.to_string(),
+            Transform::Trim => text.trim().to_string(),
+            Transform::Upper => text.to_uppercase(),
+            Transform::Lower => text.to_lowercase(),
+            Transform::Title => title_case(text),
+            Transform::CollapseWhitespace => text.split_whitespace().collect::<Vec<_>>().join(" "),
+            Transform::UrlEncode => url_encode(text),
+            Transform::UrlDecode => url_decode(text)?,
+            Transform::Base64Encode => base64.encode(text),
+            Transform::Base64Decode => {
+                let bytes = base64
+                    .decode(text.trim())
+                    .map_err(|e| format!("Not valid Base64: {}", e))?;
+                String::from_utf8(bytes).map_err(|_| "Decoded Base64 is not text")?
+            }
+            Transform::JsonPretty => reformat_json(text, true)?,
+            Transform::JsonMinify => reformat_json(text, false)?,
+            Transform::ShellEscape => format!("'{}'", text.replace('\'', "'\\''")),
+            Transform::SqlEscape => format!("'{}'", text.replace('\'', "''")),
+        })
+    }
+}
+
+fn title_case(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut word_start = true;
+
+    for c in text.chars() {
+        if word_start {
+            result.extend(c.to_uppercase());
+        } else {
+            result.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace();
+    }
+
+    result
+}
+
+/// Percent-encodes everything except the RFC 3986 unreserved characters.
+fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn url_decode(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or("Not valid URL encoding")?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| "Decoded URL is not text".into())
+}
+
+/// Re-indents JSON (two spaces, like serde_json) or strips its whitespace. Works on
+/// the text itself, so keys keep their order and numbers are written as they were.
+fn reformat_json(text: &str, pretty: bool) -> Result<String, String> {
+    serde_json::from_str::<serde::de::IgnoredAny>(text).map_err(|e| format!("Not valid JSON: {}", e))?;
+
+    let mut formatted = String::with_capacity(text.len());
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            formatted.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                formatted.push(c);
+            }
+            '{' | '[' => {
+                formatted.push(c);
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                // Empty objects and arrays stay on one line
+                let close = if c == '{' { '}' } else { ']' };
+                if let Some(close) = chars.next_if_eq(&close) {
+                    formatted.push(close);
+                } else {
+                    depth += 1;
+                    if pretty {
+                        new_line(&mut formatted, depth);
+                    }
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                if pretty {
+                    new_line(&mut formatted, depth);
+                }
+                formatted.push(c);
+            }
+            ',' => {
+                formatted.push(c);
+                if pretty {
+                    new_line(&mut formatted, depth);
+                }
+            }
+            ':' => formatted.push_str(if pretty { ": " } else { ":" }),
+            c if c.is_whitespace() => {}
+            c => formatted.push(c),
+        }
+    }
+
+    Ok(formatted)
+}
+
+fn new_line(text: &mut String, depth: usize) {
+    text.push('\n');
+    text.push_str(&"  ".repeat(depth));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(transform: Transform, text: &str) -> String {
+        transform.apply(text).unwrap()
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for transform in Transform::ALL {
+            assert_eq!(Transform::from_name(transform.name()), Some(transform));
+            assert_eq!(
+                serde_json::to_string(&transform).unwrap(),
+                format!("\"{}\"", transform.name())
+            );
+        }
+        assert_eq!(Transform::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn changes_case_and_whitespace() {
+        assert_eq!(apply(Transform::Plain, " Keep <b>as is</b> "), " Keep <b>as is</b> ");
+        assert_eq!(apply(Transform::Trim, "\n  hello world \t"), "hello world");
+        assert_eq!(apply(Transform::Upper, "straße"), "STRASSE");
+        assert_eq!(apply(Transform::Lower, "HeLLo"), "hello");
+        assert_eq!(apply(Transform::Title, "hELLO wORLD\nnew line"), "Hello World\nNew Line");
+        assert_eq!(apply(Transform::CollapseWhitespace, "  a \n\t b  c "), "a b c");
+    }
+
+    #[test]
+    fn url_encodes_and_decodes() {
+        assert_eq!(apply(Transform::UrlEncode, "a b/c?d=é~"), "a%20b%2Fc%3Fd%3D%C3%A9~");
+        assert_eq!(apply(Transform::UrlDecode, "a%20b%2Fc%3Fd%3D%C3%A9~"), "a b/c?d=é~");
+        assert_eq!(apply(Transform::UrlDecode, "a+b"), "a+b");
+    }
+
+    #[test]
+    fn rejects_invalid_url_encoding() {
+        assert!(Transform::UrlDecode.apply("100%").is_err());
+        assert!(Transform::UrlDecode.apply("%zz").is_err());
+        assert!(Transform::UrlDecode.apply("%FF").is_err());
+    }
+
+    #[test]
+    fn base64_encodes_and_decodes() {
+        assert_eq!(apply(Transform::Base64Encode, "hello"), "aGVsbG8=");
+        assert_eq!(apply(Transform::Base64Decode, " aGVsbG8=\n"), "hello");
+        assert!(Transform::Base64Decode.apply("not base64!").is_err());
+        assert!(Transform::Base64Decode.apply("/w==").is_err());
+    }
+
+    #[test]
+    fn formats_json() {
+        assert_eq!(apply(Transform::JsonPretty, r#"{"a":[1,2]}"#), "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+        assert_eq!(apply(Transform::JsonMinify, "{ \"a\" : [ 1, 2 ] }"), r#"{"a":[1,2]}"#);
+        assert!(Transform::JsonPretty.apply("{a: 1}").is_err());
+        assert!(Transform::JsonMinify.apply("").is_err());
+    }
+
+    #[test]
+    fn json_keeps_key_order_and_numbers() {
+        let minified = r#"{"zebra":1,"apple":{"mango":2.50,"banana":[]},"kiwi":1e3,"empty":{}}"#;
+        let pretty = "{\n  \"zebra\": 1,\n  \"apple\": {\n    \"mango\": 2.50,\n    \"banana\": []\n  },\n  \"kiwi\": 1e3,\n  \"empty\": {}\n}";
+
+        assert_eq!(apply(Transform::JsonPretty, minified), pretty);
+        assert_eq!(apply(Transform::JsonMinify, pretty), minified);
+    }
+
+    #[test]
+    fn json_leaves_strings_alone() {
+        let json = r#"{ "text" : "a, b: {c} [d] \"e\" \\" }"#;
+
+        assert_eq!(apply(Transform::JsonMinify, json), r#"{"text":"a, b: {c} [d] \"e\" \\"}"#);
+        assert_eq!(apply(Transform::JsonPretty, json), "{\n  \"text\": \"a, b: {c} [d] \\\"e\\\" \\\\\"\n}");
+    }
+
+    #[test]
+    fn quotes_for_shell_and_sql() {
+        assert_eq!(apply(Transform::ShellEscape, "it's $HOME"), r"'it'\''s $HOME'");
+        assert_eq!(apply(Transform::SqlEscape, "O'Brien"), "'O''Brien'");
+    }
+}
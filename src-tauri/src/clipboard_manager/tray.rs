@@ -1,19 +1,26 @@
 use tauri::{
-    include_image, menu::{CheckMenuItemBuilder, IconMenuItemBuilder, MenuBuilder, MenuItem, MenuItemBuilder}, tray::TrayIconBuilder, AppHandle, Manager
+    image::Image, include_image, menu::{CheckMenuItemBuilder, IconMenuItemBuilder, MenuBuilder, MenuItem, MenuItemBuilder, Submenu, SubmenuBuilder}, tray::TrayIconBuilder, AppHandle, Manager
 };
 
 use super::{
     handlers::{
         handle_tray_menu_event, handle_tray_icon_event
     }, 
-    history::ClipboardHistory
+    frecency::{sort_by_frecency, SortMode},
+    history::{ClipboardHistory, HistoryItem, MASKED_PLACEHOLDER},
+    queue::PasteQueue,
+    snippet_dir::{merged_bookmarks, merged_folders},
+    transform::Transform
 };
-use crate::commands::Bookmark;
+use crate::commands::{AppConfig, Bookmark, BookmarkFolder};
 use std::sync::{Arc, RwLock};
 
+/// Longest tray label, in characters; longer ones are cut and end with "..."
+const MAX_LABEL_CHARS: usize = 30;
+
 pub fn setup_tray_menu(app_handle: &AppHandle, update_tray: Option<bool>) {
     let mut menu = MenuBuilder::new(app_handle);
-    let items: Vec<String>;
+    let items: Vec<HistoryItem>;
     if let Some(_) = update_tray {
         items = app_handle.state::<Arc<RwLock<ClipboardHistory>>>().read().unwrap().get_items();
     } else {
@@ -23,11 +30,30 @@ pub fn setup_tray_menu(app_handle: &AppHandle, update_tray: Option<bool>) {
     // Load the bookmark icon *once*
     let bookmark_icon = include_image!("../src/assets/bookmark.ico");
 
+    let config = app_handle.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().clone();
+    let sort = config.tray_sort;
+
     // Create bookmark menu items
-    let bookmarks = app_handle.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().read().unwrap().to_vec();
-    let bookmark_menu_items = bookmarks_as_menu_items_for_tray(&bookmarks);
+    // Personal bookmarks and the snippet directory's
+    let mut bookmarks = merged_bookmarks(app_handle);
+    if sort == SortMode::Frecency {
+        sort_by_frecency(&mut bookmarks, |b| (b.paste_count, b.last_pasted_at));
+    }
+    let folders = merged_folders(app_handle);
+    // Anything whose folder no longer exists is shown at the top level
+    let is_top_level = |folder_id: &Option<String>| {
+        folder_id.as_ref().is_none_or(|folder_id| !folders.iter().any(|f| &f.id == folder_id))
+    };
+    let top_level_bookmarks: Vec<Bookmark> = bookmarks.iter().filter(|b| is_top_level(&b.folder_id)).cloned().collect();
+    let bookmark_menu_items = bookmarks_as_menu_items_for_tray(&top_level_bookmarks);
 
-    // Create initial empty menu
+    // Pinned entries go first, the rest keep their recency (or frecency) order
+    let latest = items.first().cloned();
+    let mut items = items;
+    if sort == SortMode::Frecency {
+        sort_by_frecency(&mut items, |item| (item.paste_count, item.last_pasted_at));
+    }
+    items.sort_by_key(|item| !item.pinned);
     let menu_items = history_as_menu_items_for_tray(&items);
 
     menu = menu.item(
@@ -37,13 +63,18 @@ pub fn setup_tray_menu(app_handle: &AppHandle, update_tray: Option<bool>) {
             .unwrap()
     );
     
+    // Folders first, as nested submenus
+    for folder in folders.iter().filter(|f| is_top_level(&f.parent_id)) {
+        menu = menu.item(&folder_submenu(app_handle, folder, &folders, &bookmarks, &bookmark_icon));
+    }
+
     // Combine bookmarks and history (bookmarks first)
     for (id, text) in bookmark_menu_items {
         let item = IconMenuItemBuilder::new(text)
             .icon(bookmark_icon.clone())
             .id(id)
             .build(app_handle).unwrap();
-        
+
         menu = menu.item(&item);
     }
 
@@ -57,23 +88,53 @@ pub fn setup_tray_menu(app_handle: &AppHandle, update_tray: Option<bool>) {
 
 
 
-    for (id, text) in menu_items {
+    for ((id, text), history_item) in menu_items.iter().zip(items.iter()) {
         let item = CheckMenuItemBuilder::new(text)
             .id(id)
-            .checked(false)
+            .checked(history_item.pinned)
             .enabled(true)
             .build(app_handle)
             .unwrap();
-        
+
         menu = menu.item(&item);
         // menu.append(&item).unwrap();
     }
 
+    // Pins (or unpins) the most recently copied entry
+    if let Some(latest) = &latest {
+        let label = if latest.pinned { "Unpin latest entry" } else { "Pin latest entry" };
+        menu = menu.separator().item(
+            &MenuItem::with_id(app_handle, "toggle_pin_latest", label, true, None::<&str>).unwrap()
+        );
+    }
+    if let Some(submenu) = transforms_submenu(app_handle, &items, &menu_items) {
+        menu = menu.item(&submenu);
+    }
+
+    // Sequential paste mode, with the number of queued copies
+    let queue = app_handle.state::<Arc<RwLock<PasteQueue>>>().inner().read().unwrap().state();
+    menu = menu.separator();
+    if queue.active {
+        menu = menu
+            .item(&MenuItem::with_id(app_handle, "paste_next", format!("Paste next ({} queued)", queue.length), queue.length > 0, None::<&str>).unwrap())
+            .item(&MenuItem::with_id(app_handle, "toggle_paste_queue", "Stop paste queue", true, None::<&str>).unwrap());
+    } else {
+        menu = menu.item(&MenuItem::with_id(app_handle, "toggle_paste_queue", "Start paste queue", true, None::<&str>).unwrap());
+    }
+
+    // Only offered once some bookmark has an abbreviation
+    if bookmarks.iter().any(|b| b.abbreviation.is_some()) {
+        let label = if config.expansion.paused { "Resume text expansion" } else { "Pause text expansion" };
+        menu = menu.item(&MenuItem::with_id(app_handle, "toggle_expansion_pause", label, true, None::<&str>).unwrap());
+    }
+
     // Add separator and quit
     let quit_item = MenuItem::with_id(app_handle, "quit", "Quit", true, None::<&str>).unwrap();
     let show_item = MenuItem::with_id(app_handle, "show", "Settings", true, None::<&str>).unwrap();
+    let clear_item = MenuItem::with_id(app_handle, "clear_history", "Clear history", !items.is_empty(), None::<&str>).unwrap();
 
     let built_menu = menu.separator()
+        .item(&clear_item)
         .item(&show_item)
         .item(&quit_item).build().unwrap();
      
@@ -117,36 +178,149 @@ pub fn setup_tray_menu(app_handle: &AppHandle, update_tray: Option<bool>) {
 
 }
 
-pub fn history_as_menu_items_for_tray(history: &Vec<String>) -> Vec<(String, String)> {
+/// Submenu of a bookmark folder, with its subfolders and then its bookmarks.
+fn folder_submenu(
+    app_handle: &AppHandle,
+    folder: &BookmarkFolder,
+    folders: &[BookmarkFolder],
+    bookmarks: &[Bookmark],
+    bookmark_icon: &Image<'_>,
+) -> Submenu<tauri::Wry> {
+    let mut submenu = SubmenuBuilder::with_id(app_handle, format!("folder_{}", folder.id), &folder.name);
+    let mut is_empty = true;
+
+    for child in folders.iter().filter(|f| f.parent_id.as_deref() == Some(folder.id.as_str())) {
+        submenu = submenu.item(&folder_submenu(app_handle, child, folders, bookmarks, bookmark_icon));
+        is_empty = false;
+    }
+
+    let folder_bookmarks: Vec<Bookmark> = bookmarks
+        .iter()
+        .filter(|b| b.folder_id.as_deref() == Some(folder.id.as_str()))
+        .cloned()
+        .collect();
+    for (id, text) in bookmarks_as_menu_items_for_tray(&folder_bookmarks) {
+        let item = IconMenuItemBuilder::new(text)
+            .icon(bookmark_icon.clone())
+            .id(id)
+            .build(app_handle)
+            .unwrap();
+        submenu = submenu.item(&item);
+        is_empty = false;
+    }
+
+    if is_empty {
+        submenu = submenu.item(&MenuItemBuilder::new("(empty)").enabled(false).build(app_handle).unwrap());
+    }
+
+    submenu.build().unwrap()
+}
+
+/// Pastes a history entry through one of the text transformations: one submenu per
+/// entry with text, all under "Paste as…" so the entries themselves stay clickable.
+/// None when no entry has text.
+fn transforms_submenu(
+    app_handle: &AppHandle,
+    items: &[HistoryItem],
+    menu_items: &[(String, String)],
+) -> Option<Submenu<tauri::Wry>> {
+    let mut submenu = SubmenuBuilder::new(app_handle, "Paste as…");
+    let mut is_empty = true;
+
+    for (item, (tray_id, text)) in items.iter().zip(menu_items) {
+        if item.masked || item.entry.text_content().is_none() {
+            continue;
+        }
+        let mut item_submenu = SubmenuBuilder::with_id(app_handle, format!("transforms_{}", tray_id), text);
+        for transform in Transform::ALL {
+            item_submenu = item_submenu.text(format!("transform:{}:{}", transform.name(), tray_id), transform.label());
+        }
+        submenu = submenu.item(&item_submenu.build().unwrap());
+        is_empty = false;
+    }
+
+    (!is_empty).then(|| submenu.build().unwrap())
+}
+
+pub fn history_as_menu_items_for_tray(history: &[HistoryItem]) -> Vec<(String, String)> {
     let mut menu_items = Vec::new();
 
     // Add clipboard history items
-    for (index, item) in history.iter().enumerate() {
-        let display_text = if item.len() > 30 {
-            format!("{}...", &item[..30])
+    for history_item in history.iter() {
+        let display_text = if history_item.masked {
+            MASKED_PLACEHOLDER.to_string()
         } else {
-            item.clone()
+            truncate_label(&history_item.entry.preview())
         };
 
-        menu_items.push((format!("item_{}", index), display_text));
+        menu_items.push((format!("item_{}", history_item.id), display_text));
     }
 
     menu_items
 }
 
-pub fn bookmarks_as_menu_items_for_tray(bookmarks: &Vec<Bookmark>) -> Vec<(String, String)> {
+pub fn bookmarks_as_menu_items_for_tray(bookmarks: &[Bookmark]) -> Vec<(String, String)> {
     let mut menu_items = Vec::new();
 
     // Add clipboard history items
-    for (index, item) in bookmarks.iter().enumerate() {
-        let display_text = if item.content.len() > 30 {
-            format!("{}...", &item.content[..30])
-        } else {
-            item.content.clone()
+    for item in bookmarks.iter() {
+        menu_items.push((format!("item_bm_{}", item.id), truncate_label(item.label())));
+    }
+
+    menu_items
+}
+
+/// Shortens `label` to MAX_LABEL_CHARS characters (not bytes, so multibyte text is never cut in half).
+fn truncate_label(label: &str) -> String {
+    match label.char_indices().nth(MAX_LABEL_CHARS) {
+        Some((end, _)) => format!("{}...", &label[..end]),
+        None => label.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard_manager::entry::ClipboardEntry;
+
+    #[test]
+    fn truncates_long_labels_by_characters() {
+        assert_eq!(truncate_label("short"), "short");
+        assert_eq!(truncate_label(&"a".repeat(30)), "a".repeat(30));
+        assert_eq!(truncate_label(&"a".repeat(31)), format!("{}...", "a".repeat(30)));
+    }
+
+    #[test]
+    fn bookmark_labels_with_multibyte_characters_do_not_panic() {
+        // "é" spans bytes 29 and 30, and each CJK character is 3 bytes
+        let cafe = Bookmark {
+            title: Some(format!("{}Café au lait", "x".repeat(26))),
+            ..Bookmark::new("coffee".into())
+        };
+        let cjk = Bookmark {
+            title: Some("剪贴板".repeat(11)),
+            ..Bookmark::new("clipboard".into())
         };
 
-        menu_items.push((format!("item_bm_{}", index), display_text));
+        let items = bookmarks_as_menu_items_for_tray(&[cafe.clone(), cjk]);
+
+        assert_eq!(items[0], (format!("item_bm_{}", cafe.id), format!("{}Café...", "x".repeat(26))));
+        assert_eq!(items[1].1, format!("{}...", "剪贴板".repeat(10)));
     }
 
-    menu_items
+    #[test]
+    fn html_and_file_previews_with_multibyte_characters_do_not_panic() {
+        let html = HistoryItem::from(ClipboardEntry::Html {
+            html: "<p>Résumé</p>".into(),
+            alt_text: Some(format!("{}Résumé final", "x".repeat(28))),
+        });
+        let files = HistoryItem::from(ClipboardEntry::FileList {
+            paths: vec![format!("/home/ana/{}🎉.png", "x".repeat(22)).into()],
+        });
+
+        let items = history_as_menu_items_for_tray(&[html, files]);
+
+        assert_eq!(items[0].1, format!("{}Ré...", "x".repeat(28)));
+        assert_eq!(items[1].1, format!("[File] {}🎉...", "x".repeat(22)));
+    }
 }
@@ -2,24 +2,157 @@
 use serde::{Deserialize, Serialize};
 use tauri::{Emitter, Manager};
 use std::fs::File;
+use std::collections::HashMap;
 use std::io::Write;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
-use crate::clipboard_manager::history::ClipboardHistory;
+use crate::clipboard_manager::entry::{read_stored_images, remove_orphan_images};
+use crate::clipboard_manager::bookmark_io::{self, BookmarkFormat, DuplicatePolicy, ImportReport};
+use crate::clipboard_manager::entry::ClipboardEntry;
+use crate::clipboard_manager::handlers::{
+    check_bookmark_hotkey, check_config_shortcuts, paste_keys, paste_text, register_keyboard_shortcuts, sync_bookmark_hotkeys,
+    unregister_keyboard_shortcuts,
+};
+use crate::validation::{validate_config, ConfigError, StartupWarnings};
+use crate::clipboard_manager::expansion::{check_abbreviation, sync_listener, ExpansionConfig};
+use crate::clipboard_manager::expiry::{prune_history, ExpiryConfig};
+use crate::clipboard_manager::filter::SensitiveFilterConfig;
+use crate::clipboard_manager::frecency::{sort_by_frecency, SortMode};
+use crate::clipboard_manager::history::{items_to_save, new_id, now_millis, ClearOptions, ClipboardHistory, HistoryItem};
+use crate::clipboard_manager::queue::{PasteQueue, PasteQueueState, QueueOrder};
+use crate::clipboard_manager::template::{self, FieldsRequest, TemplateContext};
+use crate::clipboard_manager::transform::Transform;
+use crate::clipboard_manager::search::{search, SearchFilters, SearchResult};
+use crate::clipboard_manager::snippet_dir::{self, merged_bookmarks, merged_folders, SnippetDirConfig};
 use crate::clipboard_manager::tray::setup_tray_menu;
+use crate::migrations::{BookmarksFile, FoldersFile, NewerFiles, BOOKMARKS_VERSION, CONFIG_VERSION, FOLDERS_VERSION};
+use crate::storage::{KeySource, StoreStatus, Vault};
+use crate::undo::{merge_bookmarks, merge_folders, merge_history, Change, Snapshot, UndoStack, UndoState};
 
+/// Settings saved in config.json. Settings missing from the file take their value
+/// from `AppConfig::default()`; see `migrations` for older files.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct AppConfig {
+    /// Format version of config.json
+    pub version: u32,
     pub max_items: usize,
     pub open_shortcut: String,
     pub bookmark_shortcut: String,
     pub start_minimized: bool,
+    pub persist_history: bool,
+    pub sensitive_filter: SensitiveFilterConfig,
+    pub expiry: ExpiryConfig,
+    /// Global shortcut for clearing the history; none by default
+    pub clear_shortcut: Option<String>,
+    pub paste_queue_order: QueueOrder,
+    /// Global shortcut that turns the paste queue on and off
+    pub queue_shortcut: Option<String>,
+    /// Global shortcut that pastes the next queued copy
+    pub paste_next_shortcut: Option<String>,
+    /// Order of the bookmarks and history entries in the tray
+    pub tray_sort: SortMode,
+    pub expansion: ExpansionConfig,
+    pub snippet_dir: SnippetDirConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            version: CONFIG_VERSION,
+            max_items: 10,
+            open_shortcut: "Ctrl+Super+V".into(),
+            bookmark_shortcut: "Ctrl+Super+B".into(),
+            start_minimized: false,
+            persist_history: true,
+            sensitive_filter: Default::default(),
+            expiry: Default::default(),
+            clear_shortcut: None,
+            paste_queue_order: Default::default(),
+            queue_shortcut: None,
+            paste_next_shortcut: None,
+            tray_sort: Default::default(),
+            expansion: Default::default(),
+            snippet_dir: Default::default(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Bookmark {
+    #[serde(default = "new_id")]
+    pub id: String,
     pub content: String,
+    /// Id of the history entry this bookmark was toggled from, if any
+    #[serde(default)]
+    pub history_id: Option<String>,
+    /// Shown in the tray instead of the content
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Folder the bookmark is in; None (or a folder that no longer exists) is the top level
+    #[serde(default)]
+    pub folder_id: Option<String>,
+    /// Global shortcut that pastes the bookmark
+    #[serde(default)]
+    pub hotkey: Option<String>,
+    /// Typed text (e.g. `;sig`) that is replaced by the bookmark
+    #[serde(default)]
+    pub abbreviation: Option<String>,
+    /// Path of the file behind a snippet from the snippet directory, relative to it
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Times the bookmark was pasted from the tray, a hotkey or the UI
+    #[serde(default)]
+    pub paste_count: u32,
+    #[serde(default)]
+    pub last_pasted_at: Option<u64>,
+}
+
+/// A bookmark folder, shown as a submenu in the tray. Folders are kept in folders.json.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BookmarkFolder {
+    pub id: String,
+    pub name: String,
+    /// None for top-level folders
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Path of the directory behind a folder from the snippet directory, relative to it
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl Bookmark {
+    pub fn new(content: String) -> Self {
+        Bookmark {
+            id: new_id(),
+            content,
+            history_id: None,
+            title: None,
+            description: None,
+            tags: vec![],
+            folder_id: None,
+            hotkey: None,
+            abbreviation: None,
+            file: None,
+            paste_count: 0,
+            last_pasted_at: None,
+        }
+    }
+
+    /// Title if there is one, otherwise the content.
+    pub fn label(&self) -> &str {
+        self.title
+            .as_deref()
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or(&self.content)
+    }
 }
 
 #[tauri::command]
@@ -29,75 +162,119 @@ pub fn get_config(app: tauri::AppHandle) -> Option<AppConfig> {
 }
 
 #[tauri::command]
-pub fn set_config(app: tauri::AppHandle, config: AppConfig) -> Result<(), String> {
+pub fn set_config(app: tauri::AppHandle, config: AppConfig) -> Result<(), ConfigError> {
+    let mut errors = validate_config(&config);
+    errors.extend(check_config_shortcuts(&app, &config));
+    if !errors.is_empty() {
+        return Err(ConfigError::invalid(errors));
+    }
+
     // Save the config to a file using app.app_handle()
     if let Err(e) = save_config_to_file(&app, &config) {
-        return Err(format!("Failed to save config: {}", e));
+        return Err(format!("Failed to save config: {}", e).into());
     }
 
-    if let Err(error) = crate::clipboard_manager::handlers::register_keyboard_shortcuts(&app, &config) {
-        return Err(error.to_string());
-    }
+    let old_config = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().clone();
+    unregister_keyboard_shortcuts(&app, &old_config);
+    // The settings are saved either way; shortcuts taken by other apps are reported after
+    let shortcut_errors = register_keyboard_shortcuts(&app, &config);
 
+    let max_items = config.max_items;
     let mut app_config = app.state::<Arc<RwLock<AppConfig>>>().inner().write().unwrap();
     *app_config = config;
+    // The clipboard listener locks the history before reading the config, so never hold both
+    drop(app_config);
+    sync_listener(&app);
 
     let mut history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
-    history.change_limit(app_config.max_items);
+    history.change_limit(max_items);
+
+    if let Err(e) = save_history_to_file(&app, &history) {
+        return Err(format!("Failed to save clipboard history: {}", e).into());
+    }
 
     drop(history);
-    drop(app_config);
     std::thread::spawn(move || {
+        // A shorter max age applies right away instead of on the next pruning pass
+        prune_history(app.app_handle());
+        if snippet_dir::reload(app.app_handle()) {
+            let _ = app.emit_to("main", "bookmarks-updated", ());
+        }
         setup_tray_menu(app.app_handle(), Some(true));
     });
 
+    if !shortcut_errors.is_empty() {
+        return Err(ConfigError {
+            message: "Settings saved, but some shortcuts could not be registered".into(),
+            fields: shortcut_errors,
+        });
+    }
+
     Ok(())
 }
 
+/// Problems found in config.json at startup, where the defaults were used instead.
 #[tauri::command]
-pub fn get_bookmarks(app: tauri::AppHandle) -> Option<Vec<Bookmark>> {
-    let bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().read().unwrap();
+pub fn get_startup_warnings(app: tauri::AppHandle) -> Vec<String> {
+    app.state::<Arc<RwLock<StartupWarnings>>>().inner().read().unwrap().0.clone()
+}
+
+/// Bookmarks in list order followed by the snippet directory's, or sorted by `sort`.
+#[tauri::command]
+pub fn get_bookmarks(app: tauri::AppHandle, sort: Option<SortMode>) -> Option<Vec<Bookmark>> {
     //Some(bm.to_vec())
-    Some(bookmarks.clone())
+    let mut bookmarks = merged_bookmarks(&app);
+    if sort == Some(SortMode::Frecency) {
+        sort_by_frecency(&mut bookmarks, |b| (b.paste_count, b.last_pasted_at));
+    }
+    Some(bookmarks)
 }
 
 #[tauri::command]
-pub fn remove_bookmark(app: tauri::AppHandle, index: usize) -> Result<(), String> {
+pub fn remove_bookmark(app: tauri::AppHandle, id: String) -> Result<(), String> {
     dbg!("Entramos no remove_bookmark");
+    if let Some(result) = snippet_dir::write_through(&app, &id, None) {
+        return result;
+    }
     let mut bookmarks = app
         .state::<Arc<RwLock<Vec<Bookmark>>>>()
         .inner()
         .write()
         .unwrap();
-    if index < bookmarks.len() {
+    if let Some(index) = bookmarks.iter().position(|b| b.id == id) {
+        let before = bookmarks.clone();
         bookmarks.remove(index);
+        record_change(&app, Change::bookmarks("Remove bookmark", before, bookmarks.clone()));
         // Save after removing
         if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
             return Err(format!("Failed to save bookmarks after removal: {}", e));
         }
         
         drop(bookmarks);
+        refresh_bookmark_triggers(&app);
         std::thread::spawn(move || {
             setup_tray_menu(app.app_handle(), Some(true));
         });
         
         Ok(())
     } else {
-        Err("Index out of bounds".into())
+        Err("Bookmark not found".into())
     }
 }
 
 #[tauri::command]
-pub fn add_bookmark(app: tauri::AppHandle, content: String) -> Result<(), String> {
+pub fn add_bookmark(app: tauri::AppHandle, content: String) -> Result<String, String> {
     dbg!("Entramos no add_bookmark");
     let mut bookmarks = app
         .state::<Arc<RwLock<Vec<Bookmark>>>>()
         .inner()
         .write()
         .unwrap();
-    bookmarks.push(Bookmark {
-        content: content.clone(),
-    });
+    let before = bookmarks.clone();
+    let bookmark = Bookmark::new(content);
+    let id = bookmark.id.clone();
+    bookmarks.push(bookmark);
+    record_change(&app, Change::bookmarks("Add bookmark", before, bookmarks.clone()));
 
     // Save after adding
     if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
@@ -109,6 +286,439 @@ pub fn add_bookmark(app: tauri::AppHandle, content: String) -> Result<(), String
         setup_tray_menu(app.app_handle(), Some(true));
     });
 
+    Ok(id)
+}
+
+/// Changes the content of a bookmark, keeping its id, position and details. The
+/// bookmark no longer stands for the history entry it was toggled from.
+#[tauri::command]
+pub fn update_bookmark(app: tauri::AppHandle, id: String, content: String) -> Result<(), String> {
+    if let Some(result) = snippet_dir::write_through(&app, &id, Some(&content)) {
+        return result;
+    }
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    let before = bookmarks.clone();
+    match bookmarks.iter_mut().find(|b| b.id == id) {
+        Some(bookmark) => {
+            if bookmark.content == content {
+                return Ok(());
+            }
+            bookmark.content = content;
+            bookmark.history_id = None;
+        }
+        None => return Err("Bookmark not found".into()),
+    }
+    record_change(&app, Change::bookmarks("Edit bookmark", before, bookmarks.clone()));
+
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+
+    drop(bookmarks);
+    let _ = app.emit_to("main", "bookmarks-updated", ());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(())
+}
+
+/// Moves a bookmark to `index` in the list (and in its tray section). Indexes past
+/// the end move it to the end.
+#[tauri::command]
+pub fn move_bookmark(app: tauri::AppHandle, id: String, index: usize) -> Result<(), String> {
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    let from = match bookmarks.iter().position(|b| b.id == id) {
+        Some(from) => from,
+        None => return Err("Bookmark not found".into()),
+    };
+    let to = index.min(bookmarks.len() - 1);
+    if from == to {
+        return Ok(());
+    }
+
+    let before = bookmarks.clone();
+    let bookmark = bookmarks.remove(from);
+    bookmarks.insert(to, bookmark);
+    record_change(&app, Change::bookmarks("Move bookmark", before, bookmarks.clone()));
+
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+
+    drop(bookmarks);
+    let _ = app.emit_to("main", "bookmarks-updated", ());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(())
+}
+
+/// Sets the title, description and tags of a bookmark. Empty strings clear the
+/// title and description; tags are trimmed and empty ones dropped.
+#[tauri::command]
+pub fn set_bookmark_details(
+    app: tauri::AppHandle,
+    id: String,
+    title: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+) -> Result<(), String> {
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    let before = bookmarks.clone();
+    let bookmark = match bookmarks.iter_mut().find(|b| b.id == id) {
+        Some(bookmark) => bookmark,
+        None => return Err("Bookmark not found".into()),
+    };
+
+    let non_empty = |text: Option<String>| text.map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+    bookmark.title = non_empty(title);
+    bookmark.description = non_empty(description);
+    bookmark.tags = tags
+        .into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    record_change(&app, Change::bookmarks("Edit bookmark", before, bookmarks.clone()));
+
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+
+    drop(bookmarks);
+    let _ = app.emit_to("main", "bookmarks-updated", ());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(())
+}
+
+/// Sets the global shortcut that pastes a bookmark, or removes it with None. Fails
+/// when the shortcut is invalid, already used by the app or another bookmark, or
+/// can't be registered (e.g. because another application holds it).
+#[tauri::command]
+pub fn set_bookmark_hotkey(app: tauri::AppHandle, id: String, hotkey: Option<String>) -> Result<(), String> {
+    let hotkey = hotkey.map(|key| key.trim().to_string()).filter(|key| !key.is_empty());
+    if let Some(key) = &hotkey {
+        check_bookmark_hotkey(&app, &id, key)?;
+    }
+
+    let bookmarks_state = app.state::<Arc<RwLock<Vec<Bookmark>>>>();
+    let mut bookmarks = bookmarks_state.inner().write().unwrap();
+    let before = bookmarks.clone();
+    let previous = match bookmarks.iter_mut().find(|b| b.id == id) {
+        Some(bookmark) => std::mem::replace(&mut bookmark.hotkey, hotkey.clone()),
+        None => return Err("Bookmark not found".into()),
+    };
+    if previous == hotkey {
+        return Ok(());
+    }
+    drop(bookmarks);
+
+    if let Some((_, error)) = sync_bookmark_hotkeys(&app).into_iter().find(|(failed, _)| *failed == id) {
+        let mut bookmarks = bookmarks_state.inner().write().unwrap();
+        if let Some(bookmark) = bookmarks.iter_mut().find(|b| b.id == id) {
+            bookmark.hotkey = previous;
+        }
+        drop(bookmarks);
+        refresh_bookmark_triggers(&app);
+        return Err(format!("Failed to register {}: {}", hotkey.unwrap_or_default(), error));
+    }
+
+    let bookmarks = bookmarks_state.inner().read().unwrap();
+    record_change(&app, Change::bookmarks("Set bookmark hotkey", before, bookmarks.clone()));
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+
+    drop(bookmarks);
+    let _ = app.emit_to("main", "bookmarks-updated", ());
+
+    Ok(())
+}
+
+/// Sets the abbreviation that expands into a bookmark, or removes it with None.
+#[tauri::command]
+pub fn set_bookmark_abbreviation(app: tauri::AppHandle, id: String, abbreviation: Option<String>) -> Result<(), String> {
+    let abbreviation = abbreviation.map(|text| text.trim().to_string()).filter(|text| !text.is_empty());
+    let trigger = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().expansion.trigger;
+
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    if let Some(abbreviation) = &abbreviation {
+        check_abbreviation(abbreviation, &id, &bookmarks, trigger)?;
+    }
+    let before = bookmarks.clone();
+    match bookmarks.iter_mut().find(|b| b.id == id) {
+        Some(bookmark) => {
+            if bookmark.abbreviation == abbreviation {
+                return Ok(());
+            }
+            bookmark.abbreviation = abbreviation;
+        }
+        None => return Err("Bookmark not found".into()),
+    }
+    record_change(&app, Change::bookmarks("Set bookmark abbreviation", before, bookmarks.clone()));
+
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+
+    drop(bookmarks);
+    sync_listener(&app);
+    let _ = app.emit_to("main", "bookmarks-updated", ());
+
+    Ok(())
+}
+
+/// Pauses or resumes text expansion and returns whether it is now paused.
+#[tauri::command]
+pub fn toggle_expansion_pause(app: tauri::AppHandle) -> Result<bool, String> {
+    let mut config = app.state::<Arc<RwLock<AppConfig>>>().inner().write().unwrap();
+    // Saved before it is applied, so a config.json that can't be written (e.g. one
+    // from a newer version) leaves expansion as it was
+    let mut toggled = config.clone();
+    toggled.expansion.paused = !toggled.expansion.paused;
+    let paused = toggled.expansion.paused;
+    if let Err(e) = save_config_to_file(&app, &toggled) {
+        return Err(format!("Failed to save config: {}", e));
+    }
+    *config = toggled;
+    drop(config);
+    sync_listener(&app);
+
+    let _ = app.emit_to("main", "config-updated", ());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(paused)
+}
+
+/// Brings the registered bookmark hotkeys and the abbreviation listener in line
+/// with the bookmarks.
+fn refresh_bookmark_triggers(app: &tauri::AppHandle) {
+    for (id, error) in sync_bookmark_hotkeys(app) {
+        eprintln!("Failed to register the hotkey of bookmark {}: {}", id, error);
+    }
+    sync_listener(app);
+}
+
+#[tauri::command]
+pub fn get_folders(app: tauri::AppHandle) -> Vec<BookmarkFolder> {
+    merged_folders(&app)
+}
+
+#[tauri::command]
+pub fn create_folder(app: tauri::AppHandle, name: String, parent_id: Option<String>) -> Result<String, String> {
+    let name = folder_name(&name)?;
+    let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().write().unwrap();
+    if parent_id.as_ref().is_some_and(|parent_id| !folders.iter().any(|f| &f.id == parent_id)) {
+        return Err("Parent folder not found".into());
+    }
+
+    let before = folders.clone();
+    let id = new_id();
+    folders.push(BookmarkFolder {
+        id: id.clone(),
+        name,
+        parent_id,
+        path: None,
+    });
+    record_change(&app, Change::folders("Create folder", before, folders.clone()));
+
+    save_folders(&app, folders)?;
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub fn rename_folder(app: tauri::AppHandle, id: String, name: String) -> Result<(), String> {
+    let name = folder_name(&name)?;
+    let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().write().unwrap();
+    let before = folders.clone();
+    match folders.iter_mut().find(|f| f.id == id) {
+        Some(folder) => folder.name = name,
+        None => return Err("Folder not found".into()),
+    }
+    record_change(&app, Change::folders("Rename folder", before, folders.clone()));
+
+    save_folders(&app, folders)
+}
+
+/// The trimmed folder name; blank names are refused.
+fn folder_name(name: &str) -> Result<String, String> {
+    match name.trim() {
+        "" => Err("The folder name cannot be empty".into()),
+        name => Ok(name.to_string()),
+    }
+}
+
+/// Moves a folder (with everything in it) under `parent_id`, or to the top level.
+#[tauri::command]
+pub fn move_folder(app: tauri::AppHandle, id: String, parent_id: Option<String>) -> Result<(), String> {
+    let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().write().unwrap();
+    if !folders.iter().any(|f| f.id == id) {
+        return Err("Folder not found".into());
+    }
+
+    // Walk up from the new parent: finding the folder itself means it would contain itself
+    let mut ancestor = parent_id.clone();
+    while let Some(ancestor_id) = ancestor {
+        if ancestor_id == id {
+            return Err("A folder cannot be moved into itself".into());
+        }
+        ancestor = match folders.iter().find(|f| f.id == ancestor_id) {
+            Some(folder) => folder.parent_id.clone(),
+            None => return Err("Parent folder not found".into()),
+        };
+    }
+
+    let before = folders.clone();
+    folders.iter_mut().find(|f| f.id == id).unwrap().parent_id = parent_id;
+    record_change(&app, Change::folders("Move folder", before, folders.clone()));
+
+    save_folders(&app, folders)
+}
+
+/// Deletes a folder. Its bookmarks and subfolders move up to the folder's parent.
+#[tauri::command]
+pub fn delete_folder(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    // Bookmarks are locked before folders, like everywhere else
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().write().unwrap();
+    let index = match folders.iter().position(|f| f.id == id) {
+        Some(index) => index,
+        None => return Err("Folder not found".into()),
+    };
+
+    let (folders_before, bookmarks_before) = (folders.clone(), bookmarks.clone());
+    let parent_id = folders.remove(index).parent_id;
+    for folder in folders.iter_mut().filter(|f| f.parent_id.as_deref() == Some(id.as_str())) {
+        folder.parent_id = parent_id.clone();
+    }
+    for bookmark in bookmarks.iter_mut().filter(|b| b.folder_id.as_deref() == Some(id.as_str())) {
+        bookmark.folder_id = parent_id.clone();
+    }
+    record_change(
+        &app,
+        Change::folders("Delete folder", folders_before, folders.clone())
+            .with_bookmarks(bookmarks_before, bookmarks.clone()),
+    );
+
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+    drop(bookmarks);
+
+    save_folders(&app, folders)
+}
+
+/// Puts a bookmark in a folder, or at the top level when `folder_id` is None.
+#[tauri::command]
+pub fn set_bookmark_folder(app: tauri::AppHandle, id: String, folder_id: Option<String>) -> Result<(), String> {
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    let folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().read().unwrap();
+    if folder_id.as_ref().is_some_and(|folder_id| !folders.iter().any(|f| &f.id == folder_id)) {
+        return Err("Folder not found".into());
+    }
+    drop(folders);
+
+    let before = bookmarks.clone();
+    match bookmarks.iter_mut().find(|b| b.id == id) {
+        Some(bookmark) => bookmark.folder_id = folder_id,
+        None => return Err("Bookmark not found".into()),
+    }
+    record_change(&app, Change::bookmarks("Move bookmark", before, bookmarks.clone()));
+
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+
+    drop(bookmarks);
+    let _ = app.emit_to("main", "bookmarks-updated", ());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(())
+}
+
+/// Imports bookmarks (and the folders they are in) from a file or directory. With
+/// `dry_run` nothing is changed and the report tells what the import would do.
+#[tauri::command]
+pub fn import_bookmarks(
+    app: tauri::AppHandle,
+    path: String,
+    format: BookmarkFormat,
+    duplicates: DuplicatePolicy,
+    dry_run: bool,
+) -> Result<ImportReport, String> {
+    let mut report = ImportReport {
+        dry_run,
+        ..Default::default()
+    };
+    let imported = match bookmark_io::read(std::path::Path::new(&path), format, &mut report.unsupported) {
+        Ok(imported) => imported,
+        Err(e) => return Err(format!("Failed to read {}: {}", path, e)),
+    };
+
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().write().unwrap();
+    if dry_run {
+        bookmark_io::merge(&mut bookmarks.clone(), &mut folders.clone(), imported, duplicates, &mut report);
+        return Ok(report);
+    }
+
+    // Folders created by the import go away with its bookmarks on undo
+    let (before, folders_before) = (bookmarks.clone(), folders.clone());
+    bookmark_io::merge(&mut bookmarks, &mut folders, imported, duplicates, &mut report);
+    record_change(
+        &app,
+        Change::folders("Import bookmarks", folders_before, folders.clone())
+            .with_bookmarks(before, bookmarks.clone()),
+    );
+
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+    drop(bookmarks);
+    refresh_bookmark_triggers(&app);
+    save_folders(&app, folders)?;
+
+    Ok(report)
+}
+
+/// Writes all bookmarks to `path`. For the directory format `path` is the directory to fill.
+#[tauri::command]
+pub fn export_bookmarks(app: tauri::AppHandle, path: String, format: BookmarkFormat) -> Result<(), String> {
+    let bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().read().unwrap().clone();
+    let folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().read().unwrap().clone();
+
+    if let Err(e) = bookmark_io::write(std::path::Path::new(&path), format, &bookmarks, &folders) {
+        return Err(format!("Failed to export bookmarks: {}", e));
+    }
+
+    Ok(())
+}
+
+/// Saves the folders, releases the lock and refreshes the UI and the tray.
+fn save_folders(
+    app: &tauri::AppHandle,
+    folders: std::sync::RwLockWriteGuard<'_, Vec<BookmarkFolder>>,
+) -> Result<(), String> {
+    if let Err(e) = save_folders_to_file(app, &folders) {
+        return Err(format!("Failed to save folders: {}", e));
+    }
+
+    drop(folders);
+    let _ = app.emit_to("main", "bookmarks-updated", ());
+    let app = app.clone();
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
     Ok(())
 }
 
@@ -124,6 +734,9 @@ fn save_config_to_file(
     let config_dir = app.path().app_local_data_dir().unwrap();
 
     let config_file = config_dir.join("config.json");
+    if app.state::<Arc<RwLock<NewerFiles>>>().inner().read().unwrap().config {
+        return Err("config.json is from a newer version of clipbored and is kept as it is".into());
+    }
 
     let serialized_config = serde_json::to_string(config)?;
 
@@ -140,38 +753,147 @@ fn save_bookmark_to_file(
     let config_dir = app.path().app_local_data_dir().unwrap();
 
     let bookmark_file = config_dir.join("bookmarks.json");
+    if app.state::<Arc<RwLock<NewerFiles>>>().inner().read().unwrap().bookmarks {
+        return Err("bookmarks.json is from a newer version of clipbored and is kept as it is".into());
+    }
+
+    let serialized_bookmark = serde_json::to_string(&BookmarksFile {
+        version: BOOKMARKS_VERSION,
+        bookmarks,
+    })?;
+
+    let vault = app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap();
+    vault.write(&bookmark_file, serialized_bookmark.as_bytes())?;
+
+    Ok(())
+}
+
+fn save_folders_to_file(
+    app: &tauri::AppHandle,
+    folders: &Vec<BookmarkFolder>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config_dir = app.path().app_local_data_dir().unwrap();
+
+    let folders_file = config_dir.join("folders.json");
+    if app.state::<Arc<RwLock<NewerFiles>>>().inner().read().unwrap().folders {
+        return Err("folders.json is from a newer version of clipbored and is kept as it is".into());
+    }
 
-    let serialized_bookmark = serde_json::to_string(bookmarks)?;
+    let serialized_folders = serde_json::to_string(&FoldersFile {
+        version: FOLDERS_VERSION,
+        folders,
+    })?;
 
-    let mut file = File::create(bookmark_file)?;
-    file.write_all(serialized_bookmark.as_bytes())?;
+    let vault = app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap();
+    vault.write(&folders_file, serialized_folders.as_bytes())?;
 
     Ok(())
 }
 
+/// Writes the clipboard history next to the bookmarks, or removes the saved
+/// copy when the user opted out of persisting it.
+pub fn save_history_to_file(
+    app: &tauri::AppHandle,
+    history: &ClipboardHistory,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config_dir = app.path().app_local_data_dir().unwrap();
+
+    let history_file = config_dir.join("history.json");
+
+    // A locked store only has what was copied since the start in memory: cleaning up
+    // images against that would delete the ones of the saved history
+    if app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap().is_locked() {
+        return Err("The encrypted store is locked".into());
+    }
+
+    let items = history.get_items();
+    let persist_history = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().persist_history;
+    if !persist_history {
+        if history_file.exists() {
+            std::fs::remove_file(history_file)?;
+        }
+    } else {
+        let vault = app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap();
+        let serialized_history = serde_json::to_string(&items_to_save(&items, vault.status().encrypted))?;
+        vault.write(&history_file, serialized_history.as_bytes())?;
+    }
+
+    // Images of deleted entries stay on disk while the deletion can still be undone
+    let undoable_entries = app.state::<Arc<RwLock<UndoStack>>>().inner().read().unwrap().entries();
+    remove_orphan_images(app, items.iter().map(|item| &item.entry).chain(undoable_entries.iter()))?;
+
+    Ok(())
+}
+
+/// Every file the vault protects, decrypted, so they can be rewritten under a new key.
+fn read_stored_files(
+    app: &tauri::AppHandle,
+    vault: &Vault,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, Box<dyn std::error::Error>> {
+    let config_dir = app.path().app_local_data_dir().unwrap();
+
+    let mut files = read_stored_images(app, vault)?;
+    for name in ["history.json", "bookmarks.json", "folders.json"] {
+        let path = config_dir.join(name);
+        if path.exists() {
+            let contents = vault.read_for_rekey(&path)?;
+            files.push((path, contents));
+        }
+    }
+
+    Ok(files)
+}
+
 #[tauri::command]
-pub fn get_clipboard_items(app: tauri::AppHandle) -> Vec<String> {
-    let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
-    history.get_items()
+pub fn get_clipboard_items(app: tauri::AppHandle) -> Vec<HistoryItem> {
+    let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().read().unwrap();
+    history.get_items().iter().map(HistoryItem::redacted).collect()
+}
+
+#[tauri::command]
+pub fn search_clipboard(
+    app: tauri::AppHandle,
+    query: String,
+    filters: Option<SearchFilters>,
+) -> Vec<SearchResult> {
+    let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().read().unwrap().get_items();
+    let bookmarks = merged_bookmarks(&app);
+
+    search(&query, &filters.unwrap_or_default(), &history, &bookmarks)
 }
 
 #[tauri::command]
-pub fn toggle_bookmark(app: tauri::AppHandle, content: String) -> Result<(), String> {
-    // 1. Check if it's already a bookmark.
+pub fn toggle_bookmark(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    // Read the history entry first: the clipboard listener locks history before bookmarks
+    let history_item = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().read().unwrap().get_item(&id);
+
+    // 1. Check if it's already a bookmark, either by its own id or the history entry it came from.
     let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
-    let bookmark_index = bookmarks.iter().position(|b| b.content == content);
+    let bookmark_index = bookmarks
+        .iter()
+        .position(|b| b.id == id || b.history_id.as_deref() == Some(id.as_str()));
+    let before = bookmarks.clone();
 
     if let Some(index) = bookmark_index {
         // 2. If it exists, remove it from bookmarks.
         bookmarks.remove(index);
+        record_change(&app, Change::bookmarks("Remove bookmark", before, bookmarks.clone()));
         if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
             return Err(format!("Failed to save bookmarks after removal: {}", e));
         } else {
             println!("Deu certo desregistrar o bookmark");
         }
     } else {
-        // 3. If it doesn't exist, add it to bookmarks.
-        bookmarks.push(Bookmark { content: content.clone() });
+        // 3. If it doesn't exist, bookmark the history entry with that id.
+        let content = match history_item.and_then(|item| item.entry.text_content()) {
+            Some(content) => content,
+            None => return Err("Clipboard item not found or has no text".into()),
+        };
+        bookmarks.push(Bookmark {
+            history_id: Some(id),
+            ..Bookmark::new(content)
+        });
+        record_change(&app, Change::bookmarks("Add bookmark", before, bookmarks.clone()));
         if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
             return Err(format!("Failed to save bookmarks after adding: {}", e));
         } else {
@@ -180,6 +902,7 @@ pub fn toggle_bookmark(app: tauri::AppHandle, content: String) -> Result<(), Str
     }
     
     drop(bookmarks);
+    refresh_bookmark_triggers(&app);
     app.emit_to("main", "bookmarks-updated", ()).unwrap();
     std::thread::spawn(move || {
         setup_tray_menu(app.app_handle(), Some(true));
@@ -189,14 +912,410 @@ pub fn toggle_bookmark(app: tauri::AppHandle, content: String) -> Result<(), Str
 }
 
 #[tauri::command]
-pub fn delete_clipboard_item(app: tauri::AppHandle, item: String) -> Result<(), String> {
+pub fn delete_clipboard_item(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
+    let before = history.get_items();
+    history.remove_item(&id);
+    record_change(&app, Change::history("Delete clipboard item", before, history.get_items()));
+
+    if let Err(e) = save_history_to_file(&app, &history) {
+        return Err(format!("Failed to save clipboard history after removal: {}", e));
+    }
+
+    drop(history);
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(())
+}
+
+/// Removes history entries, keeping the ones selected by `options` (pinned entries by
+/// default). The tray item and the global shortcut go through here too.
+#[tauri::command]
+pub fn clear_history(app: tauri::AppHandle, options: Option<ClearOptions>) -> Result<(), String> {
+    let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
+    let before = history.get_items();
+    if !history.clear(&options.unwrap_or_default()) {
+        return Ok(());
+    }
+    record_change(&app, Change::history("Clear history", before, history.get_items()));
+
+    if let Err(e) = save_history_to_file(&app, &history) {
+        return Err(format!("Failed to save clipboard history: {}", e));
+    }
+
+    drop(history);
+    let _ = app.emit_to("main", "clipboard-updated", ());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(())
+}
+
+/// Pastes a bookmark or history entry into the focused window, optionally
+/// transformed. Tray items go through here too.
+///
+/// Bookmark placeholders are expanded first. If the bookmark has `{prompt:...}`
+/// fields missing from `fields`, nothing is pasted: the window is shown and a
+/// `snippet-fields-requested` event asks the UI for them.
+#[tauri::command]
+pub fn paste_item(
+    app: tauri::AppHandle,
+    id: String,
+    transform: Option<Transform>,
+    fields: Option<HashMap<String, String>>,
+) -> Result<(), String> {
+    paste_entry(app, id, transform, fields, true)
+}
+
+/// Pastes a bookmark or history entry into the window that has the focus, for
+/// bookmark hotkeys and abbreviations, which are used without leaving that window.
+pub fn paste_in_focused_window(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    paste_entry(app, id, None, None, false)
+}
+
+/// `return_to_previous_window` first switches away from clipbored's own window (on
+/// Windows), which the tray and the UI leave in front.
+fn paste_entry(
+    app: tauri::AppHandle,
+    id: String,
+    transform: Option<Transform>,
+    fields: Option<HashMap<String, String>>,
+    return_to_previous_window: bool,
+) -> Result<(), String> {
+    let bookmark = merged_bookmarks(&app).into_iter().find(|b| b.id == id);
+    let entry = match bookmark {
+        Some(bookmark) => {
+            let fields = fields.unwrap_or_default();
+            let missing: Vec<String> = template::prompt_fields(&bookmark.content)
+                .into_iter()
+                .filter(|field| !fields.contains_key(field))
+                .collect();
+            if !missing.is_empty() {
+                app.get_webview_window("main").unwrap().show().unwrap();
+                let _ = app.emit_to("main", "snippet-fields-requested", FieldsRequest { id, fields: missing, transform });
+                return Ok(());
+            }
+
+            // Read with arboard: the clipboard plugin must not read from the main thread
+            let clipboard = template::uses_clipboard(&bookmark.content)
+                .then(|| arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()).ok())
+                .flatten();
+            let context = TemplateContext { clipboard, fields: &fields };
+            ClipboardEntry::from(template::expand(&bookmark.content, &context))
+        }
+        None => match app.state::<Arc<RwLock<ClipboardHistory>>>().inner().read().unwrap().get_item(&id) {
+            Some(item) => item.entry,
+            None => return Err("Item not found".into()),
+        },
+    };
+
+    match transform {
+        Some(transform) => {
+            let text = entry.text_content().ok_or("This item has no text to transform")?;
+            let text = transform.apply(&text)?;
+            if let Err(e) = ClipboardEntry::from(text).restore(&app) {
+                return Err(format!("Failed to write to the clipboard: {}", e));
+            }
+        }
+        None => {
+            if let Err(e) = entry.restore(&app) {
+                return Err(format!("Failed to restore clipboard item: {}", e));
+            }
+        }
+    }
+
+    let _ = if return_to_previous_window { paste_text() } else { paste_keys() };
+    record_paste(&app, &id)?;
+
+    Ok(())
+}
+
+/// Counts a paste of a bookmark or history entry, for the frecency sort.
+fn record_paste(app: &tauri::AppHandle, id: &str) -> Result<(), String> {
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    if let Some(index) = bookmarks.iter().position(|b| b.id == id) {
+        let bookmark = &mut bookmarks[index];
+        let previous = (bookmark.paste_count, bookmark.last_pasted_at);
+        bookmark.paste_count += 1;
+        bookmark.last_pasted_at = Some(now_millis());
+        if let Err(e) = save_bookmark_to_file(app, &bookmarks) {
+            // E.g. a bookmarks.json from a newer version: the count stays as saved
+            (bookmarks[index].paste_count, bookmarks[index].last_pasted_at) = previous;
+            return Err(format!("Failed to save bookmarks: {}", e));
+        }
+        drop(bookmarks);
+        let _ = app.emit_to("main", "bookmarks-updated", ());
+    } else {
+        drop(bookmarks);
+        if let Some(result) = snippet_dir::record_paste(app, id) {
+            result?;
+            let _ = app.emit_to("main", "bookmarks-updated", ());
+        } else {
+            let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().read().unwrap();
+            if history.record_paste(id) {
+                if let Err(e) = save_history_to_file(app, &history) {
+                    return Err(format!("Failed to save clipboard history: {}", e));
+                }
+            }
+        }
+    }
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_paste_queue(app: tauri::AppHandle) -> PasteQueueState {
+    app.state::<Arc<RwLock<PasteQueue>>>().inner().read().unwrap().state()
+}
+
+/// Turns sequential paste mode on or off and returns whether it is now on.
+#[tauri::command]
+pub fn toggle_paste_queue(app: tauri::AppHandle) -> bool {
+    let mut queue = app.state::<Arc<RwLock<PasteQueue>>>().inner().write().unwrap();
+    let active = queue.toggle();
+    let state = queue.state();
+    drop(queue);
+
+    let _ = app.emit_to("main", "paste-queue-updated", state);
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    active
+}
+
+/// Puts the next queued copy on the clipboard and pastes it into the focused window.
+#[tauri::command]
+pub fn paste_next(app: tauri::AppHandle) -> Result<PasteQueueState, String> {
+    let order = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().paste_queue_order;
+    let queue_state = app.state::<Arc<RwLock<PasteQueue>>>();
+
+    // Entries deleted from the history since they were queued are skipped
+    let item = loop {
+        let id = match queue_state.inner().write().unwrap().pop(order) {
+            Some(id) => id,
+            None => return Err("The paste queue is empty".into()),
+        };
+        let item = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().read().unwrap().get_item(&id);
+        if let Some(item) = item {
+            break item;
+        }
+    };
+
+    let state = queue_state.inner().read().unwrap().state();
+
+    if let Err(e) = item.entry.restore(&app) {
+        return Err(format!("Failed to restore clipboard item: {}", e));
+    }
+    let _ = paste_text();
+    record_paste(&app, &item.id)?;
+
+    let _ = app.emit_to("main", "paste-queue-updated", state.clone());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(state)
+}
+
+/// Pins or unpins a history entry and returns whether it is now pinned.
+#[tauri::command]
+pub fn toggle_pin(app: tauri::AppHandle, id: String) -> Result<bool, String> {
     let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
-    history.remove_item(item);
+    let pinned = match history.toggle_pin(&id) {
+        Some(pinned) => pinned,
+        None => return Err("Clipboard item not found".into()),
+    };
+
+    if let Err(e) = save_history_to_file(&app, &history) {
+        return Err(format!("Failed to save clipboard history: {}", e));
+    }
+
+    drop(history);
+    let _ = app.emit_to("main", "clipboard-updated", ());
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(pinned)
+}
+
+#[tauri::command]
+pub fn get_store_status(app: tauri::AppHandle) -> StoreStatus {
+    app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap().status()
+}
 
+/// Unlocks a passphrase-protected store and loads the bookmarks and history kept in it.
+#[tauri::command]
+pub fn unlock_store(app: tauri::AppHandle, passphrase: String) -> Result<(), String> {
+    let mut vault = app.state::<Arc<RwLock<Vault>>>().inner().write().unwrap();
+    if let Err(e) = vault.unlock(Some(&passphrase)) {
+        return Err(e.to_string());
+    }
+    drop(vault);
+
+    let persist_history = app.state::<Arc<RwLock<AppConfig>>>().inner().read().unwrap().persist_history;
+    let (stored_bookmarks, stored_history) = crate::load_stored_data(&app, persist_history);
+
+    let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().read().unwrap();
+    // Anything copied while the store was locked is newer than what was saved
+    history.append_items(stored_history);
+    if let Err(e) = save_history_to_file(&app, &history) {
+        return Err(format!("Failed to save clipboard history: {}", e));
+    }
     drop(history);
+
+    let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+    let added_while_locked = std::mem::replace(&mut *bookmarks, stored_bookmarks);
+    bookmarks.extend(added_while_locked);
+    if let Err(e) = save_bookmark_to_file(&app, &bookmarks) {
+        return Err(format!("Failed to save bookmarks: {}", e));
+    }
+    drop(bookmarks);
+    refresh_bookmark_triggers(&app);
+
+    let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().write().unwrap();
+    let added_while_locked = std::mem::replace(&mut *folders, crate::load_stored_folders(&app));
+    folders.extend(added_while_locked);
+    if let Err(e) = save_folders_to_file(&app, &folders) {
+        return Err(format!("Failed to save folders: {}", e));
+    }
+    drop(folders);
+
+    let _ = app.emit_to("main", "clipboard-updated", ());
+    let _ = app.emit_to("main", "bookmarks-updated", ());
     std::thread::spawn(move || {
         setup_tray_menu(app.app_handle(), Some(true));
     });
 
     Ok(())
 }
+
+/// Enables encryption, changes its passphrase or key file, or turns it off when
+/// neither is given. Every stored file is re-encrypted with the new key.
+#[tauri::command]
+pub fn set_store_encryption(
+    app: tauri::AppHandle,
+    passphrase: Option<String>,
+    key_file: Option<String>,
+) -> Result<StoreStatus, String> {
+    let key_source = match (&passphrase, key_file) {
+        (_, Some(path)) => Some(KeySource::KeyFile { path: path.into() }),
+        (Some(_), None) => Some(KeySource::Passphrase),
+        (None, None) => None,
+    };
+
+    let mut vault = app.state::<Arc<RwLock<Vault>>>().inner().write().unwrap();
+    // The files have to be read with the old key before it is replaced
+    let files = match read_stored_files(&app, &vault) {
+        Ok(files) => files,
+        Err(e) => return Err(format!("Failed to read the stored files: {}", e)),
+    };
+    if let Err(e) = vault.rekey(key_source, passphrase.as_deref(), &files) {
+        return Err(format!("Failed to re-encrypt the store: {}", e));
+    }
+
+    Ok(vault.status())
+}
+
+/// Remembers a change so it can be undone and tells the UI an undo is available.
+pub fn record_change(app: &tauri::AppHandle, change: Change) {
+    let mut undo_stack = app.state::<Arc<RwLock<UndoStack>>>().inner().write().unwrap();
+    undo_stack.record(change);
+    let state = undo_stack.state();
+    drop(undo_stack);
+
+    let _ = app.emit_to("main", "undo-available", state);
+}
+
+#[tauri::command]
+pub fn get_undo_state(app: tauri::AppHandle) -> UndoState {
+    app.state::<Arc<RwLock<UndoStack>>>().inner().read().unwrap().state()
+}
+
+#[tauri::command]
+pub fn undo(app: tauri::AppHandle) -> Result<UndoState, String> {
+    let change = app.state::<Arc<RwLock<UndoStack>>>().inner().write().unwrap().take_undo();
+    match change {
+        Some(change) => apply_change(&app, change, UndoStack::push_redo),
+        None => Err("Nothing to undo".into()),
+    }
+}
+
+#[tauri::command]
+pub fn redo(app: tauri::AppHandle) -> Result<UndoState, String> {
+    let change = app.state::<Arc<RwLock<UndoStack>>>().inner().write().unwrap().take_redo();
+    match change {
+        Some(change) => apply_change(&app, change, UndoStack::push_undo),
+        None => Err("Nothing to redo".into()),
+    }
+}
+
+/// Puts the `after` side of `change` in place, then hands the change to `keep`
+/// (the redo or undo stack) before saving, so images it refers to are not removed.
+fn apply_change(
+    app: &tauri::AppHandle,
+    change: Change,
+    keep: fn(&mut UndoStack, Change),
+) -> Result<UndoState, String> {
+    let undo_stack = app.state::<Arc<RwLock<UndoStack>>>();
+
+    match change.snapshot.clone() {
+        Snapshot::History { before, after } => {
+            let history = app.state::<Arc<RwLock<ClipboardHistory>>>().inner().write().unwrap();
+            history.set_items(merge_history(history.get_items(), &before, after));
+            keep(&mut undo_stack.inner().write().unwrap(), change);
+
+            if let Err(e) = save_history_to_file(app, &history) {
+                return Err(format!("Failed to save clipboard history: {}", e));
+            }
+            drop(history);
+            let _ = app.emit_to("main", "clipboard-updated", ());
+        }
+        Snapshot::Bookmarks { before, after } => {
+            let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+            *bookmarks = merge_bookmarks(bookmarks.clone(), &before, after);
+            keep(&mut undo_stack.inner().write().unwrap(), change);
+
+            if let Err(e) = save_bookmark_to_file(app, &bookmarks) {
+                return Err(format!("Failed to save bookmarks: {}", e));
+            }
+            drop(bookmarks);
+            refresh_bookmark_triggers(app);
+            let _ = app.emit_to("main", "bookmarks-updated", ());
+        }
+        Snapshot::Folders { before, after, bookmarks_before, bookmarks_after } => {
+            // Bookmarks are locked before folders, like everywhere else
+            let mut bookmarks = app.state::<Arc<RwLock<Vec<Bookmark>>>>().inner().write().unwrap();
+            let mut folders = app.state::<Arc<RwLock<Vec<BookmarkFolder>>>>().inner().write().unwrap();
+            *bookmarks = merge_bookmarks(bookmarks.clone(), &bookmarks_before, bookmarks_after);
+            *folders = merge_folders(folders.clone(), &before, after);
+            keep(&mut undo_stack.inner().write().unwrap(), change);
+
+            if let Err(e) = save_bookmark_to_file(app, &bookmarks) {
+                return Err(format!("Failed to save bookmarks: {}", e));
+            }
+            drop(bookmarks);
+            refresh_bookmark_triggers(app);
+            save_folders(app, folders)?;
+        }
+    }
+
+    let state = undo_stack.inner().read().unwrap().state();
+    let _ = app.emit_to("main", "undo-available", state.clone());
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        setup_tray_menu(app.app_handle(), Some(true));
+    });
+
+    Ok(state)
+}
@@ -1,18 +1,34 @@
 mod clipboard_manager;
 mod commands;
+mod migrations;
+mod storage;
+mod undo;
+mod validation;
 
-use std::{str::FromStr, sync::{Arc, RwLock}};
+use std::sync::{Arc, RwLock};
 use tauri_plugin_autostart::{MacosLauncher, ManagerExt};
 
-use clipboard_manager::{history::ClipboardHistory, tray::setup_tray_menu};
-use tauri::{App, Manager};
+use clipboard_manager::{
+    entry::{ClipboardEntry, RestoredEntry},
+    expansion::ExpansionListener,
+    handlers::{register_keyboard_shortcuts, sync_bookmark_hotkeys, BookmarkHotkeys},
+    history::{ClipboardHistory, HistoryItem},
+    queue::PasteQueue,
+    snippet_dir::{self, SnippetDir},
+    tray::setup_tray_menu,
+};
+use tauri::{App, AppHandle, Manager};
 
-use commands::{AppConfig, Bookmark};
-use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut}; // Removed APP_CONFIG and BOOKMARKS
+use commands::{AppConfig, Bookmark, BookmarkFolder};
+use migrations::NewerFiles;
+use storage::Vault;
+use undo::UndoStack;
+use validation::StartupWarnings;
 
 use std::fs;
 
-fn load_file_configs(app: &App) -> (AppConfig, Vec<Bookmark>) {
+/// Also returns warnings about the settings that were replaced by their defaults.
+fn load_file_configs(app: &App) -> (AppConfig, Vec<Bookmark>, Vec<HistoryItem>, Vec<String>) {
     // Changed to return the values
     let save_path = app
         .path()
@@ -20,43 +36,106 @@ fn load_file_configs(app: &App) -> (AppConfig, Vec<Bookmark>) {
         .expect("Failed to get data directory"); // Moved inside the function
     let config_path = save_path.join("config.json");
 
+    // A missing file (first start) gives the defaults
+    let mut warnings = Vec::new();
     let app_config = match fs::read_to_string(&config_path) {
         Ok(contents) => {
-            serde_json::from_str(&contents).unwrap_or_else(|_| {
-                // Defaults
-                AppConfig {
-                    max_items: 10,
-                    open_shortcut: "Ctrl+Super+V".into(),
-                    bookmark_shortcut: "Ctrl+Super+B".into(),
-                    start_minimized: false,
-                }
-            })
-        }
-        Err(_) => {
-            // Defaults
-            AppConfig {
-                max_items: 10,
-                open_shortcut: "Ctrl+Super+V".into(),
-                bookmark_shortcut: "Ctrl+Super+B".into(),
-                start_minimized: false,
+            if migrations::is_newer(&contents, migrations::CONFIG_VERSION) {
+                app.state::<Arc<RwLock<NewerFiles>>>().inner().write().unwrap().config = true;
+                warnings.push("config.json is from a newer version of clipbored, settings can't be saved until it is updated".into());
             }
+            let (app_config, invalid) = migrations::parse_config(&contents);
+            warnings.extend(invalid.iter().map(|name| format!("{} could not be read, using the default", name)));
+            app_config
         }
+        Err(_) => AppConfig::default(),
     };
+    let (app_config, errors) = validation::sanitize_config(app_config);
+    warnings.extend(errors.iter().map(|e| format!("{}: {}, using the default", e.field, e.message)));
     
     dbg!("Loaded app config: {:?}", &app_config);
 
+    let (bookmarks, history) = load_stored_data(app.handle(), app_config.persist_history);
+    if app.state::<Arc<RwLock<NewerFiles>>>().inner().read().unwrap().bookmarks {
+        warnings.push("bookmarks.json is from a newer version of clipbored, bookmarks can't be saved until it is updated".into());
+    }
+
+    (app_config, bookmarks, history, warnings) // Return the loaded values
+}
+
+
+
+/// Reads bookmarks.json and history.json through the vault, so encrypted files
+/// are decrypted. A locked store yields empty lists until it is unlocked.
+pub(crate) fn load_stored_data(app: &AppHandle, persist_history: bool) -> (Vec<Bookmark>, Vec<HistoryItem>) {
+    let save_path = app
+        .path()
+        .app_local_data_dir()
+        .expect("Failed to get data directory");
+    let vault = app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap();
+
     let bookmark_path = save_path.join("bookmarks.json"); // Corrected path
-    let bookmarks = match fs::read_to_string(&bookmark_path) {
-        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|_| vec![]),
+    let bookmarks = match vault.read_to_string(&bookmark_path) {
+        Ok(contents) => {
+            let newer = migrations::is_newer(&contents, migrations::BOOKMARKS_VERSION);
+            app.state::<Arc<RwLock<NewerFiles>>>().inner().write().unwrap().bookmarks = newer;
+            migrations::parse_bookmarks(&contents).unwrap_or_else(|_| vec![])
+        }
         Err(_) => {
             vec![]
         }
     };
 
-    (app_config, bookmarks) // Return the loaded values
+    let history_path = save_path.join("history.json");
+    let history = if persist_history {
+        match vault.read_to_string(&history_path) {
+            Ok(contents) => parse_saved_history(&contents),
+            Err(_) => vec![],
+        }
+    } else {
+        vec![]
+    };
+
+    (bookmarks, history)
 }
 
+/// Reads folders.json through the vault. Empty while the store is locked.
+pub(crate) fn load_stored_folders(app: &AppHandle) -> Vec<BookmarkFolder> {
+    let folders_path = app
+        .path()
+        .app_local_data_dir()
+        .expect("Failed to get data directory")
+        .join("folders.json");
+    let vault = app.state::<Arc<RwLock<Vault>>>().inner().read().unwrap();
+
+    match vault.read_to_string(&folders_path) {
+        Ok(contents) => {
+            let newer = migrations::is_newer(&contents, migrations::FOLDERS_VERSION);
+            app.state::<Arc<RwLock<NewerFiles>>>().inner().write().unwrap().folders = newer;
+            migrations::parse_folders(&contents).unwrap_or_else(|_| vec![])
+        }
+        Err(_) => vec![],
+    }
+}
 
+/// Reads history.json, including files written by older versions that stored
+/// bare entries (without metadata) or plain strings.
+fn parse_saved_history(contents: &str) -> Vec<HistoryItem> {
+    if let Ok(items) = serde_json::from_str::<Vec<HistoryItem>>(contents) {
+        return items;
+    }
+    if let Ok(entries) = serde_json::from_str::<Vec<ClipboardEntry>>(contents) {
+        return entries.into_iter().map(HistoryItem::from).collect();
+    }
+    serde_json::from_str::<Vec<String>>(contents)
+        .map(|texts| {
+            texts
+                .into_iter()
+                .map(|text| HistoryItem::from(ClipboardEntry::from(text)))
+                .collect()
+        })
+        .unwrap_or_else(|_| vec![])
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -69,8 +148,15 @@ pub fn run() {
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build()) // Add the global shortcut plugin
         .setup(|app: &mut App| {
+            // The vault has to be in place before anything is read from disk
+            let data_dir = app.path().app_local_data_dir().expect("Failed to get data directory");
+            let vault = Vault::load(&data_dir);
+            let store_locked = vault.is_locked();
+            app.manage(Arc::new(RwLock::new(vault)));
+            app.manage(Arc::new(RwLock::new(NewerFiles::default())));
+
             // Load configs and manage state
-            let (app_config, bookmarks) = load_file_configs(app);
+            let (app_config, bookmarks, history, mut warnings) = load_file_configs(app);
             let config = app_config.clone();
 
             //// sets up the autostart function
@@ -87,7 +173,17 @@ pub fn run() {
 
             app.manage(Arc::new(RwLock::new(app_config)));
             app.manage(Arc::new(RwLock::new(bookmarks)));
-            app.manage(Arc::new(RwLock::new(ClipboardHistory::new(config.max_items))));
+            app.manage(Arc::new(RwLock::new(load_stored_folders(app.handle()))));
+            if app.state::<Arc<RwLock<NewerFiles>>>().inner().read().unwrap().folders {
+                warnings.push("folders.json is from a newer version of clipbored, folders can't be saved until it is updated".into());
+            }
+            app.manage(Arc::new(RwLock::new(ClipboardHistory::with_items(config.max_items, history))));
+            app.manage(Arc::new(RwLock::new(UndoStack::default())));
+            app.manage(Arc::new(RwLock::new(PasteQueue::default())));
+            app.manage(Arc::new(RwLock::new(RestoredEntry::default())));
+            app.manage(Arc::new(RwLock::new(BookmarkHotkeys::default())));
+            app.manage(Arc::new(RwLock::new(SnippetDir::load(app.handle()))));
+            snippet_dir::reload(app.handle());
 
             //// Sets up the tray menu
             setup_tray_menu(&app.handle(), None);
@@ -102,22 +198,36 @@ pub fn run() {
                 
             });
 
+            //// Prunes history entries older than the configured max age
+            clipboard_manager::expiry::start_pruning(app.handle().to_owned());
 
-            let global_shortcut_manager = app.global_shortcut();
-            let open_key = config.open_shortcut.as_str().replace("Meta", "Super");
-            let bookmark_key = config.bookmark_shortcut.as_str().replace("Meta", "Super");
-            
-            let open_shortcut = Shortcut::from_str(&open_key).unwrap();
-            let bookmark_shortcut = Shortcut::from_str(&bookmark_key).unwrap();
+            //// Reloads the snippet directory when its files change
+            snippet_dir::start_watching(app.handle().to_owned());
+
+            //// Expands abbreviations typed in any application into their bookmarks
+            app.manage(Arc::new(RwLock::new(ExpansionListener::default())));
+            clipboard_manager::expansion::sync_listener(app.handle());
 
-            let _ = global_shortcut_manager.on_shortcut(open_shortcut, clipboard_manager::handlers::open_shortcut_handler);
-            let _ = global_shortcut_manager.on_shortcut(bookmark_shortcut, clipboard_manager::handlers::bookmark_shortcut_handler);
+
+            //// Registers the shortcuts; one taken by another app is skipped with a warning
+            for error in register_keyboard_shortcuts(app.handle(), &config) {
+                warnings.push(format!("{}: {}", error.field, error.message));
+            }
+            for (id, error) in sync_bookmark_hotkeys(app.handle()) {
+                warnings.push(format!("The hotkey of bookmark {} was not registered: {}", id, error));
+            }
+            for warning in &warnings {
+                eprintln!("{}", warning);
+            }
+            let has_warnings = !warnings.is_empty();
+            app.manage(Arc::new(RwLock::new(StartupWarnings(warnings))));
 
             //app.manage(global_shortcut_manager);
 
 
-            //// Hides the window if that is the configuration
-            if config.start_minimized {
+            //// Hides the window if that is the configuration (a locked store needs the window to ask for the passphrase,
+            //// and warnings need it to be seen)
+            if config.start_minimized && !store_locked && !has_warnings {
                 let _ = app.get_webview_window("main").unwrap().hide();
             }
 
@@ -128,12 +238,40 @@ pub fn run() {
             commands::remove_bookmark,
             commands::get_bookmarks,
             commands::add_bookmark,
+            commands::set_bookmark_details,
+            commands::set_bookmark_hotkey,
+            commands::set_bookmark_abbreviation,
+            commands::toggle_expansion_pause,
+            commands::update_bookmark,
+            commands::move_bookmark,
+            commands::get_folders,
+            commands::create_folder,
+            commands::rename_folder,
+            commands::move_folder,
+            commands::delete_folder,
+            commands::set_bookmark_folder,
+            commands::import_bookmarks,
+            commands::export_bookmarks,
             commands::set_config,
             commands::get_config,
+            commands::get_startup_warnings,
             commands::hide_window,
             commands::get_clipboard_items, // Add the new command
             commands::toggle_bookmark,    // Add the new command
-            commands::delete_clipboard_item // Add for future use
+            commands::delete_clipboard_item, // Add for future use
+            commands::toggle_pin,
+            commands::clear_history,
+            commands::paste_item,
+            commands::get_paste_queue,
+            commands::toggle_paste_queue,
+            commands::paste_next,
+            commands::search_clipboard,
+            commands::get_store_status,
+            commands::unlock_store,
+            commands::set_store_encryption,
+            commands::undo,
+            commands::redo,
+            commands::get_undo_state
         ))
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
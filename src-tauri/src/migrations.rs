@@ -0,0 +1,240 @@
+//! Versions of config.json, bookmarks.json and folders.json. Files are upgraded step by step as
+//! JSON values before they are deserialized, so each step only has to know the
+//! version right before it. Files written before versioning are version 0.
+
+use serde_json::{Map, Value};
+
+use crate::clipboard_manager::history::new_id;
+use crate::commands::{AppConfig, Bookmark, BookmarkFolder};
+
+pub const CONFIG_VERSION: u32 = 1;
+pub const BOOKMARKS_VERSION: u32 = 1;
+pub const FOLDERS_VERSION: u32 = 1;
+
+/// Upgrades a file from version N to N + 1, at index N.
+type Migration = fn(&mut Value);
+
+const CONFIG_MIGRATIONS: [Migration; CONFIG_VERSION as usize] = [config_v0_to_v1];
+const BOOKMARKS_MIGRATIONS: [Migration; BOOKMARKS_VERSION as usize] = [bookmarks_v0_to_v1];
+const FOLDERS_MIGRATIONS: [Migration; FOLDERS_VERSION as usize] = [folders_v0_to_v1];
+
+/// Files written by a newer version of clipbored. They are read as far as this
+/// version understands them but never written back, which would drop the rest.
+#[derive(Default)]
+pub struct NewerFiles {
+    pub config: bool,
+    pub bookmarks: bool,
+    pub folders: bool,
+}
+
+/// bookmarks.json as it is written since version 1.
+#[derive(serde::Serialize)]
+pub struct BookmarksFile<'a> {
+    pub version: u32,
+    pub bookmarks: &'a [Bookmark],
+}
+
+/// folders.json as it is written since version 1.
+#[derive(serde::Serialize)]
+pub struct FoldersFile<'a> {
+    pub version: u32,
+    pub folders: &'a [BookmarkFolder],
+}
+
+/// Reads config.json. Settings that are missing or can't be read keep their
+/// default, without affecting the others; their names are returned.
+pub fn parse_config(contents: &str) -> (AppConfig, Vec<String>) {
+    let mut value = match serde_json::from_str::<Value>(contents) {
+        Ok(value) => value,
+        Err(_) => return (AppConfig::default(), vec!["config.json".into()]),
+    };
+    migrate(&mut value, &CONFIG_MIGRATIONS);
+
+    let Value::Object(fields) = value else {
+        return (AppConfig::default(), vec!["config.json".into()]);
+    };
+    let mut config = match serde_json::to_value(AppConfig::default()) {
+        Ok(Value::Object(defaults)) => defaults,
+        _ => Map::new(),
+    };
+    let mut invalid = Vec::new();
+    for (name, field) in fields {
+        let mut candidate = config.clone();
+        candidate.insert(name.clone(), field);
+        if serde_json::from_value::<AppConfig>(Value::Object(candidate.clone())).is_ok() {
+            config = candidate;
+        } else {
+            invalid.push(name);
+        }
+    }
+
+    let config = serde_json::from_value(Value::Object(config)).unwrap_or_default();
+    (config, invalid)
+}
+
+/// Reads bookmarks.json, from any version.
+pub fn parse_bookmarks(contents: &str) -> Result<Vec<Bookmark>, serde_json::Error> {
+    let mut value = serde_json::from_str::<Value>(contents)?;
+    migrate(&mut value, &BOOKMARKS_MIGRATIONS);
+
+    serde_json::from_value(value.get_mut("bookmarks").map(Value::take).unwrap_or_default())
+}
+
+/// Reads folders.json, from any version.
+pub fn parse_folders(contents: &str) -> Result<Vec<BookmarkFolder>, serde_json::Error> {
+    let mut value = serde_json::from_str::<Value>(contents)?;
+    migrate(&mut value, &FOLDERS_MIGRATIONS);
+
+    serde_json::from_value(value.get_mut("folders").map(Value::take).unwrap_or_default())
+}
+
+/// Whether `contents` was written by a newer version of the file than `current`.
+pub fn is_newer(contents: &str, current: u32) -> bool {
+    serde_json::from_str::<Value>(contents).is_ok_and(|value| version_of(&value) > current as usize)
+}
+
+/// Upgrades `value` to the last version. A newer file is left as it is.
+fn migrate(value: &mut Value, migrations: &[Migration]) {
+    let version = version_of(value);
+    if version > migrations.len() {
+        return;
+    }
+    for migration in migrations.iter().skip(version) {
+        migration(value);
+    }
+    if let Value::Object(fields) = value {
+        fields.insert("version".into(), Value::from(migrations.len()));
+    }
+}
+
+/// Version 0 files have no version; bookmarks.json was a bare list then.
+fn version_of(value: &Value) -> usize {
+    value.get("version").and_then(Value::as_u64).unwrap_or(0) as usize
+}
+
+/// Shortcuts were saved with "Meta", which global shortcuts call "Super".
+fn config_v0_to_v1(value: &mut Value) {
+    for name in ["openShortcut", "bookmarkShortcut", "clearShortcut", "queueShortcut", "pasteNextShortcut"] {
+        if let Some(Value::String(shortcut)) = value.get_mut(name) {
+            *shortcut = shortcut.replace("Meta", "Super");
+        }
+    }
+}
+
+/// The bare list becomes `{ "version": 1, "bookmarks": [...] }` and bookmarks
+/// without an id get one, so it no longer changes on every start.
+fn bookmarks_v0_to_v1(value: &mut Value) {
+    let Value::Array(bookmarks) = value else {
+        return;
+    };
+    for bookmark in bookmarks.iter_mut() {
+        if let Value::Object(fields) = bookmark {
+            fields.entry("id").or_insert_with(|| Value::from(new_id()));
+        }
+    }
+
+    *value = serde_json::json!({ "bookmarks": value.take() });
+}
+
+/// The bare list becomes `{ "version": 1, "folders": [...] }`.
+fn folders_v0_to_v1(value: &mut Value) {
+    if value.is_array() {
+        *value = serde_json::json!({ "folders": value.take() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn upgrades_config_shortcuts_from_version_0() {
+        let mut value = json!({ "openShortcut": "Ctrl+Meta+V", "clearShortcut": "Meta+Shift+X", "maxItems": 5 });
+
+        migrate(&mut value, &CONFIG_MIGRATIONS);
+
+        assert_eq!(
+            value,
+            json!({ "version": 1, "openShortcut": "Ctrl+Super+V", "clearShortcut": "Super+Shift+X", "maxItems": 5 })
+        );
+    }
+
+    #[test]
+    fn leaves_current_files_alone() {
+        let mut value = json!({ "version": 1, "openShortcut": "Ctrl+Meta+V" });
+
+        migrate(&mut value, &CONFIG_MIGRATIONS);
+
+        assert_eq!(value, json!({ "version": 1, "openShortcut": "Ctrl+Meta+V" }));
+    }
+
+    #[test]
+    fn never_downgrades_newer_files() {
+        let newer = json!({ "version": 7, "openShortcut": "Ctrl+Meta+V", "fromTheFuture": true });
+        let mut value = newer.clone();
+
+        migrate(&mut value, &CONFIG_MIGRATIONS);
+
+        assert_eq!(value, newer);
+        assert!(is_newer(&newer.to_string(), CONFIG_VERSION));
+        assert!(!is_newer(r#"{ "version": 1 }"#, CONFIG_VERSION));
+        assert!(!is_newer("[]", BOOKMARKS_VERSION));
+        assert!(!is_newer("not json", CONFIG_VERSION));
+    }
+
+    #[test]
+    fn wraps_bare_bookmark_lists_and_assigns_ids() {
+        let mut value = json!([{ "id": "kept", "content": "a" }, { "content": "b" }]);
+
+        migrate(&mut value, &BOOKMARKS_MIGRATIONS);
+
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["bookmarks"][0]["id"], "kept");
+        assert!(value["bookmarks"][1]["id"].as_str().is_some_and(|id| !id.is_empty()));
+    }
+
+    #[test]
+    fn parses_bookmarks_from_any_version() {
+        let v0 = parse_bookmarks(r#"[{ "content": "a" }]"#).unwrap();
+        let v1 = parse_bookmarks(r#"{ "version": 1, "bookmarks": [{ "id": "x", "content": "b" }] }"#).unwrap();
+        let newer = parse_bookmarks(r#"{ "version": 2, "bookmarks": [{ "id": "y", "content": "c", "color": "red" }] }"#).unwrap();
+
+        assert_eq!(v0[0].content, "a");
+        assert_eq!((v1[0].id.as_str(), v1[0].content.as_str()), ("x", "b"));
+        assert_eq!((newer[0].id.as_str(), newer[0].content.as_str()), ("y", "c"));
+        assert!(parse_bookmarks("{").is_err());
+    }
+
+    #[test]
+    fn parses_folders_from_any_version() {
+        let v0 = parse_folders(r#"[{ "id": "a", "name": "Work" }]"#).unwrap();
+        let v1 = parse_folders(r#"{ "version": 1, "folders": [{ "id": "b", "name": "Home", "parentId": "a" }] }"#).unwrap();
+
+        assert_eq!((v0[0].id.as_str(), v0[0].name.as_str()), ("a", "Work"));
+        assert_eq!(v1[0].parent_id.as_deref(), Some("a"));
+        assert!(is_newer(r#"{ "version": 2, "folders": [] }"#, FOLDERS_VERSION));
+    }
+
+    #[test]
+    fn config_settings_fall_back_one_by_one() {
+        let (config, invalid) =
+            parse_config(r#"{ "maxItems": "lots", "persistHistory": false, "openShortcut": "Meta+Alt+V" }"#);
+
+        assert_eq!(invalid, vec!["maxItems"]);
+        assert_eq!(config.max_items, AppConfig::default().max_items);
+        assert!(!config.persist_history);
+        assert_eq!(config.open_shortcut, "Super+Alt+V");
+        assert_eq!(config.version, CONFIG_VERSION);
+    }
+
+    #[test]
+    fn unreadable_config_gets_the_defaults() {
+        for contents in ["{ not json", "[1, 2]"] {
+            let (config, invalid) = parse_config(contents);
+
+            assert_eq!(invalid, vec!["config.json"]);
+            assert_eq!(config.max_items, AppConfig::default().max_items);
+        }
+    }
+}
@@ -0,0 +1,519 @@
+//! Optional encryption at rest for the files kept in app_local_data_dir().
+//!
+//! When enabled, every file written through [`Vault::write`] is sealed with
+//! XChaCha20-Poly1305 using a key derived (Argon2id) from a passphrase or from the
+//! contents of a key file. The salt and the key source live in `vault.json`, which
+//! is the only file (besides config.json) that stays in plaintext.
+//!
+//! The file name is authenticated along with the contents, so sealed files can't be
+//! swapped with each other, and once the store is encrypted, files that aren't
+//! sealed are refused instead of being loaded as they are.
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const VAULT_FILE: &str = "vault.json";
+
+/// Prefix of every encrypted file, followed by the nonce and the ciphertext
+const MAGIC: &[u8] = b"CLIPBORED-ENC2";
+/// Files sealed before the file name was authenticated. Still read, and sealed
+/// again with MAGIC the next time they are written.
+const MAGIC_V1: &[u8] = b"CLIPBORED-ENC1";
+const NONCE_LEN: usize = 24;
+
+/// Known plaintext used to tell a wrong passphrase apart from a corrupted file
+const CHECK_PLAINTEXT: &[u8] = b"clipbored";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum KeySource {
+    Passphrase,
+    KeyFile { path: PathBuf },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct VaultFile {
+    key_source: KeySource,
+    salt: Vec<u8>,
+    check: Vec<u8>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreStatus {
+    pub encrypted: bool,
+    pub unlocked: bool,
+    pub key_source: Option<KeySource>,
+}
+
+pub struct Vault {
+    dir: PathBuf,
+    settings: Option<VaultFile>,
+    key: Option<[u8; 32]>,
+}
+
+impl Vault {
+    /// Reads vault.json from `dir`. Stores protected by a key file are unlocked right
+    /// away; passphrase-protected stores stay locked until [`Vault::unlock`] is called.
+    pub fn load(dir: &Path) -> Vault {
+        let settings = fs::read_to_string(dir.join(VAULT_FILE))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<VaultFile>(&contents).ok());
+
+        let mut vault = Vault {
+            dir: dir.to_path_buf(),
+            settings,
+            key: None,
+        };
+
+        let uses_key_file = matches!(
+            &vault.settings,
+            Some(VaultFile { key_source: KeySource::KeyFile { .. }, .. })
+        );
+        if uses_key_file {
+            if let Err(e) = vault.unlock(None) {
+                eprintln!("Failed to unlock the store with its key file: {}", e);
+            }
+        }
+
+        vault
+    }
+
+    pub fn status(&self) -> StoreStatus {
+        StoreStatus {
+            encrypted: self.settings.is_some(),
+            unlocked: !self.is_locked(),
+            key_source: self.settings.as_ref().map(|settings| settings.key_source.clone()),
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.settings.is_some() && self.key.is_none()
+    }
+
+    /// Derives the key from `passphrase` (or the configured key file) and checks it.
+    pub fn unlock(&mut self, passphrase: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let settings = self.settings.as_ref().ok_or("The store is not encrypted")?;
+        let key = derive_key(&settings.key_source, passphrase, &settings.salt)?;
+
+        if open_with(&key, VAULT_FILE, &settings.check).ok().as_deref() != Some(CHECK_PLAINTEXT) {
+            return Err("Wrong passphrase or key file".into());
+        }
+
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Switches to a new key (or to plaintext when `key_source` is None) and rewrites
+    /// `files`, given as decrypted contents, under it. Every file is written next to
+    /// its target first and vault.json is switched last; if anything fails, the
+    /// files are put back, so the store stays readable with the old key.
+    pub fn rekey(
+        &mut self,
+        key_source: Option<KeySource>,
+        passphrase: Option<&str>,
+        files: &[(PathBuf, Vec<u8>)],
+    ) -> Result<(), Box<dyn Error>> {
+        if self.is_locked() {
+            return Err("The encrypted store is locked".into());
+        }
+
+        let (settings, key) = match key_source {
+            Some(key_source) => {
+                let mut salt = vec![0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                let key = derive_key(&key_source, passphrase, &salt)?;
+                let settings = VaultFile {
+                    key_source,
+                    check: seal_with(&key, VAULT_FILE, CHECK_PLAINTEXT)?,
+                    salt,
+                };
+                (Some(settings), Some(key))
+            }
+            None => (None, None),
+        };
+
+        let mut rewrite = Rewrite::default();
+        let result = rewrite.run(&self.dir, settings.as_ref(), key.as_ref(), files);
+        rewrite.finish(result.is_ok());
+        result?;
+
+        self.settings = settings;
+        self.key = key;
+
+        Ok(())
+    }
+
+    /// Reads a file, decrypting it if needed. While the store is encrypted, a file
+    /// that isn't sealed is refused: it can only have been put there from outside.
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        if !is_sealed(&data) && self.settings.is_some() {
+            return Err(format!("{} is not encrypted", path.display()).into());
+        }
+
+        self.open(path, data)
+    }
+
+    /// Like [`Vault::read`], but also returns plaintext files while the store is
+    /// encrypted. Only for [`Vault::rekey`], which seals every file it is given.
+    pub fn read_for_rekey(&self, path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        self.open(path, data)
+    }
+
+    fn open(&self, path: &Path, data: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        if !is_sealed(&data) {
+            return Ok(data);
+        }
+
+        let key = self.key.as_ref().ok_or("The encrypted store is locked")?;
+        open_with(key, &file_name(path), &data)
+    }
+
+    pub fn read_to_string(&self, path: &Path) -> Result<String, Box<dyn Error>> {
+        Ok(String::from_utf8(self.read(path)?)?)
+    }
+
+    /// Writes a file, encrypting it when the store is encrypted. Refuses to write
+    /// while locked, so the encrypted files are never replaced by empty state.
+    pub fn write(&self, path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+        if self.is_locked() {
+            return Err("The encrypted store is locked".into());
+        }
+
+        match &self.key {
+            Some(key) => fs::write(path, seal_with(key, &file_name(path), contents)?)?,
+            None => fs::write(path, contents)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Files replaced by [`Vault::rekey`], so they can be put back if it fails.
+#[derive(Default)]
+struct Rewrite {
+    /// New contents not moved into place yet
+    staged: Vec<PathBuf>,
+    /// Target and backup of the old contents (None if there was no file)
+    replaced: Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+impl Rewrite {
+    fn run(
+        &mut self,
+        dir: &Path,
+        settings: Option<&VaultFile>,
+        key: Option<&[u8; 32]>,
+        files: &[(PathBuf, Vec<u8>)],
+    ) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+
+        let mut targets = Vec::new();
+        for (path, contents) in files {
+            let sealed = match key {
+                Some(key) => seal_with(key, &file_name(path), contents)?,
+                None => contents.clone(),
+            };
+            targets.push((path.clone(), self.stage(path, &sealed)?));
+        }
+        let vault_path = dir.join(VAULT_FILE);
+        let vault_contents = match settings {
+            Some(settings) => Some(self.stage(&vault_path, serde_json::to_string(settings)?.as_bytes())?),
+            None => None,
+        };
+
+        for (path, staged) in targets {
+            self.replace(&path, Some(&staged))?;
+        }
+        // Switching vault.json makes the new key the one in use
+        self.replace(&vault_path, vault_contents.as_deref())
+    }
+
+    fn stage(&mut self, path: &Path, contents: &[u8]) -> Result<PathBuf, Box<dyn Error>> {
+        let staged = with_suffix(path, "rekey");
+        fs::write(&staged, contents)?;
+        self.staged.push(staged.clone());
+
+        Ok(staged)
+    }
+
+    /// Moves `staged` to `path`, or just removes `path` when there is nothing staged,
+    /// keeping the old file as a backup.
+    fn replace(&mut self, path: &Path, staged: Option<&Path>) -> Result<(), Box<dyn Error>> {
+        let backup = if path.exists() {
+            let backup = with_suffix(path, "bak");
+            fs::rename(path, &backup)?;
+            Some(backup)
+        } else {
+            None
+        };
+        self.replaced.push((path.to_path_buf(), backup));
+
+        if let Some(staged) = staged {
+            fs::rename(staged, path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the backups after a successful rekey, or puts them back after a
+    /// failed one. Staged files left over are deleted either way.
+    fn finish(self, succeeded: bool) {
+        for (path, backup) in self.replaced.into_iter().rev() {
+            let Some(backup) = backup else {
+                if !succeeded {
+                    let _ = fs::remove_file(path);
+                }
+                continue;
+            };
+            let result = if succeeded {
+                fs::remove_file(&backup)
+            } else {
+                fs::rename(&backup, &path)
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to clean up {}: {}", backup.display(), e);
+            }
+        }
+        for staged in self.staged {
+            if staged.exists() {
+                let _ = fs::remove_file(staged);
+            }
+        }
+    }
+}
+
+/// `history.json` becomes `history.json.<suffix>`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(suffix);
+
+    PathBuf::from(name)
+}
+
+fn derive_key(
+    key_source: &KeySource,
+    passphrase: Option<&str>,
+    salt: &[u8],
+) -> Result<[u8; 32], Box<dyn Error>> {
+    let secret = match key_source {
+        KeySource::Passphrase => passphrase
+            .filter(|passphrase| !passphrase.is_empty())
+            .ok_or("A passphrase is required")?
+            .as_bytes()
+            .to_vec(),
+        KeySource::KeyFile { path } => fs::read(path)?,
+    };
+
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(&secret, salt, &mut key)
+        .map_err(|e| format!("Failed to derive the encryption key: {}", e))?;
+
+    Ok(key)
+}
+
+/// The name a file is sealed under, authenticated as associated data.
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC) || data.starts_with(MAGIC_V1)
+}
+
+fn seal_with(key: &[u8; 32], name: &str, plaintext: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad: name.as_bytes() })
+        .map_err(|_| "Failed to encrypt data")?;
+
+    let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(MAGIC);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(sealed)
+}
+
+fn open_with(key: &[u8; 32], name: &str, sealed: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (body, aad) = match sealed.strip_prefix(MAGIC) {
+        Some(body) => (Some(body), name.as_bytes()),
+        None => (sealed.strip_prefix(MAGIC_V1), &[][..]),
+    };
+    let body = body
+        .filter(|body| body.len() >= NONCE_LEN)
+        .ok_or("Not an encrypted clipbored file")?;
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| "Failed to decrypt data: wrong key or corrupted file")?;
+
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("clipbored-test-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A store in `dir` encrypted with the passphrase "secret", holding `files`.
+    fn encrypted(dir: &Path, files: &[(PathBuf, Vec<u8>)]) -> Vault {
+        let mut vault = Vault::load(dir);
+        vault.rekey(Some(KeySource::Passphrase), Some("secret"), files).unwrap();
+        vault
+    }
+
+    #[test]
+    fn sealed_files_round_trip() {
+        let dir = TempDir::new();
+        let path = dir.0.join("history.json");
+        let vault = encrypted(&dir.0, &[]);
+
+        vault.write(&path, b"[\"copied\"]").unwrap();
+
+        let on_disk = fs::read(&path).unwrap();
+        assert!(on_disk.starts_with(MAGIC));
+        assert!(!on_disk.windows(6).any(|window| window == b"copied"));
+        assert_eq!(vault.read(&path).unwrap(), b"[\"copied\"]");
+    }
+
+    #[test]
+    fn rekey_encrypts_existing_files() {
+        let dir = TempDir::new();
+        let path = dir.0.join("bookmarks.json");
+        fs::write(&path, b"plain").unwrap();
+
+        let vault = encrypted(&dir.0, &[(path.clone(), b"plain".to_vec())]);
+
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+        assert_eq!(vault.read(&path).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn unlocking_needs_the_right_passphrase() {
+        let dir = TempDir::new();
+        let path = dir.0.join("history.json");
+        encrypted(&dir.0, &[]).write(&path, b"data").unwrap();
+
+        let mut vault = Vault::load(&dir.0);
+        assert!(vault.is_locked());
+        assert!(vault.read(&path).is_err());
+        assert!(vault.write(&path, b"empty").is_err());
+        assert!(vault.unlock(Some("wrong")).is_err());
+        assert!(vault.is_locked());
+
+        vault.unlock(Some("secret")).unwrap();
+        assert_eq!(vault.read(&path).unwrap(), b"data");
+    }
+
+    #[test]
+    fn tampered_files_fail_to_open() {
+        let dir = TempDir::new();
+        let path = dir.0.join("history.json");
+        let vault = encrypted(&dir.0, &[]);
+        vault.write(&path, b"data").unwrap();
+
+        let mut sealed = fs::read(&path).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        fs::write(&path, sealed).unwrap();
+
+        assert!(vault.read(&path).is_err());
+    }
+
+    #[test]
+    fn sealed_files_cannot_be_swapped() {
+        let dir = TempDir::new();
+        let (history, bookmarks) = (dir.0.join("history.json"), dir.0.join("bookmarks.json"));
+        let vault = encrypted(&dir.0, &[]);
+        vault.write(&history, b"history").unwrap();
+        vault.write(&bookmarks, b"bookmarks").unwrap();
+
+        fs::copy(&history, &bookmarks).unwrap();
+
+        assert!(vault.read(&bookmarks).is_err());
+    }
+
+    #[test]
+    fn plaintext_is_refused_while_encrypted() {
+        let dir = TempDir::new();
+        let path = dir.0.join("history.json");
+        let vault = encrypted(&dir.0, &[]);
+
+        fs::write(&path, b"[\"planted\"]").unwrap();
+
+        assert!(vault.read(&path).is_err());
+        // Turning encryption on or changing the key still takes such files in
+        assert_eq!(vault.read_for_rekey(&path).unwrap(), b"[\"planted\"]");
+    }
+
+    #[test]
+    fn plaintext_is_read_when_not_encrypted() {
+        let dir = TempDir::new();
+        let path = dir.0.join("history.json");
+        let vault = Vault::load(&dir.0);
+
+        vault.write(&path, b"plain").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"plain");
+        assert_eq!(vault.read(&path).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn failed_rekey_puts_everything_back() {
+        let dir = TempDir::new();
+        let (history, bookmarks) = (dir.0.join("history.json"), dir.0.join("bookmarks.json"));
+        let mut vault = encrypted(&dir.0, &[]);
+        vault.write(&history, b"history").unwrap();
+        vault.write(&bookmarks, b"bookmarks").unwrap();
+        let vault_json = fs::read(dir.0.join(VAULT_FILE)).unwrap();
+        let sealed_history = fs::read(&history).unwrap();
+
+        // bookmarks.json can't be backed up onto a directory that isn't empty, so it
+        // fails after history.json was already replaced
+        let blocker = with_suffix(&bookmarks, "bak");
+        fs::create_dir_all(blocker.join("inside")).unwrap();
+        let files = [(history.clone(), b"history".to_vec()), (bookmarks.clone(), b"bookmarks".to_vec())];
+
+        assert!(vault.rekey(Some(KeySource::Passphrase), Some("other"), &files).is_err());
+
+        assert_eq!(fs::read(dir.0.join(VAULT_FILE)).unwrap(), vault_json);
+        assert_eq!(fs::read(&history).unwrap(), sealed_history);
+        assert!(!with_suffix(&history, "bak").exists());
+        assert!(!with_suffix(&history, "rekey").exists());
+        assert!(!with_suffix(&bookmarks, "rekey").exists());
+        // Still usable with the old key, in memory and after a restart
+        assert_eq!(vault.read(&bookmarks).unwrap(), b"bookmarks");
+        let mut reloaded = Vault::load(&dir.0);
+        reloaded.unlock(Some("secret")).unwrap();
+        assert_eq!(reloaded.read(&history).unwrap(), b"history");
+    }
+}
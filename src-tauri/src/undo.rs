@@ -0,0 +1,341 @@
+//! Undo/redo for destructive operations on the history and the bookmarks.
+//!
+//! Each change keeps the affected list as it was before and after the operation.
+//! Undoing puts the "before" list back, but anything that was added since (a new
+//! copy, a new bookmark) is kept, so undo never throws away newer data.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use crate::clipboard_manager::entry::ClipboardEntry;
+use crate::clipboard_manager::history::HistoryItem;
+use crate::commands::{Bookmark, BookmarkFolder};
+
+/// How many changes can be undone
+const MAX_UNDO_DEPTH: usize = 50;
+
+#[derive(Debug, Clone)]
+pub enum Snapshot {
+    History {
+        before: Vec<HistoryItem>,
+        after: Vec<HistoryItem>,
+    },
+    Bookmarks {
+        before: Vec<Bookmark>,
+        after: Vec<Bookmark>,
+    },
+    /// Folders, with the bookmarks that changed along with them (moved out of a
+    /// deleted folder, imported into new ones). Both bookmark lists are empty when
+    /// no bookmark changed.
+    Folders {
+        before: Vec<BookmarkFolder>,
+        after: Vec<BookmarkFolder>,
+        bookmarks_before: Vec<Bookmark>,
+        bookmarks_after: Vec<Bookmark>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// Shown in the UI, e.g. "Delete clipboard item"
+    pub label: String,
+    pub snapshot: Snapshot,
+}
+
+impl Change {
+    pub fn history(label: &str, before: Vec<HistoryItem>, after: Vec<HistoryItem>) -> Self {
+        Change {
+            label: label.into(),
+            snapshot: Snapshot::History { before, after },
+        }
+    }
+
+    pub fn bookmarks(label: &str, before: Vec<Bookmark>, after: Vec<Bookmark>) -> Self {
+        Change {
+            label: label.into(),
+            snapshot: Snapshot::Bookmarks { before, after },
+        }
+    }
+
+    pub fn folders(label: &str, before: Vec<BookmarkFolder>, after: Vec<BookmarkFolder>) -> Self {
+        Change {
+            label: label.into(),
+            snapshot: Snapshot::Folders {
+                before,
+                after,
+                bookmarks_before: vec![],
+                bookmarks_after: vec![],
+            },
+        }
+    }
+
+    /// Adds the bookmarks changed along with the folders, so one undo restores both.
+    pub fn with_bookmarks(mut self, before: Vec<Bookmark>, after: Vec<Bookmark>) -> Self {
+        if let Snapshot::Folders { bookmarks_before, bookmarks_after, .. } = &mut self.snapshot {
+            *bookmarks_before = before;
+            *bookmarks_after = after;
+        }
+        self
+    }
+
+    /// The same change seen from the other side, used to move it between the stacks.
+    fn reversed(self) -> Self {
+        let snapshot = match self.snapshot {
+            Snapshot::History { before, after } => Snapshot::History { before: after, after: before },
+            Snapshot::Bookmarks { before, after } => Snapshot::Bookmarks { before: after, after: before },
+            Snapshot::Folders { before, after, bookmarks_before, bookmarks_after } => Snapshot::Folders {
+                before: after,
+                after: before,
+                bookmarks_before: bookmarks_after,
+                bookmarks_after: bookmarks_before,
+            },
+        };
+        Change { label: self.label, snapshot }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoState {
+    pub can_undo: bool,
+    pub can_redo: bool,
+    pub undo_label: Option<String>,
+    pub redo_label: Option<String>,
+}
+
+/// Never lock another piece of managed state while holding this one.
+#[derive(Default)]
+pub struct UndoStack {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+}
+
+impl UndoStack {
+    /// Records a new change. Anything that could be redone is forgotten.
+    pub fn record(&mut self, change: Change) {
+        self.redo.clear();
+        self.push_undo(change);
+    }
+
+    /// Takes the latest change out, already reversed: applying its `after` undoes it.
+    pub fn take_undo(&mut self) -> Option<Change> {
+        self.undo.pop_back().map(Change::reversed)
+    }
+
+    pub fn take_redo(&mut self) -> Option<Change> {
+        self.redo.pop().map(Change::reversed)
+    }
+
+    /// Stores an undone change (as returned by take_undo) so it can be redone.
+    pub fn push_redo(&mut self, change: Change) {
+        self.redo.push(change);
+    }
+
+    /// Stores a redone or newly recorded change so it can be undone.
+    pub fn push_undo(&mut self, change: Change) {
+        self.undo.push_back(change);
+        while self.undo.len() > MAX_UNDO_DEPTH {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn state(&self) -> UndoState {
+        UndoState {
+            can_undo: !self.undo.is_empty(),
+            can_redo: !self.redo.is_empty(),
+            undo_label: self.undo.back().map(|change| change.label.clone()),
+            redo_label: self.redo.last().map(|change| change.label.clone()),
+        }
+    }
+
+    /// Entries that could come back through undo or redo, so their stored images are kept.
+    pub fn entries(&self) -> Vec<ClipboardEntry> {
+        self.undo
+            .iter()
+            .chain(self.redo.iter())
+            .flat_map(|change| match &change.snapshot {
+                Snapshot::History { before, after } => before.iter().chain(after.iter()).collect(),
+                Snapshot::Bookmarks { .. } | Snapshot::Folders { .. } => vec![],
+            })
+            .map(|item| item.entry.clone())
+            .collect()
+    }
+}
+
+/// Replaces `replaced` with `replacement` in `current`. History entries copied after
+/// the change stay in front, unless the same content comes back with the replacement.
+pub fn merge_history(
+    current: Vec<HistoryItem>,
+    replaced: &[HistoryItem],
+    replacement: Vec<HistoryItem>,
+) -> Vec<HistoryItem> {
+    let newer: Vec<HistoryItem> = current
+        .into_iter()
+        .filter(|item| !replaced.iter().any(|x| x.id == item.id))
+        .collect();
+    let replacement = replacement
+        .into_iter()
+        .filter(|item| !newer.iter().any(|x| x.id == item.id || x.entry == item.entry));
+
+    newer.iter().cloned().chain(replacement).collect()
+}
+
+/// Replaces `replaced` with `replacement` in `current`. Bookmarks added after the
+/// change are kept at the end.
+pub fn merge_bookmarks(
+    current: Vec<Bookmark>,
+    replaced: &[Bookmark],
+    replacement: Vec<Bookmark>,
+) -> Vec<Bookmark> {
+    merge_by_id(current, replaced, replacement, |bookmark| &bookmark.id)
+}
+
+/// Same as [`merge_bookmarks`], for folders.
+pub fn merge_folders(
+    current: Vec<BookmarkFolder>,
+    replaced: &[BookmarkFolder],
+    replacement: Vec<BookmarkFolder>,
+) -> Vec<BookmarkFolder> {
+    merge_by_id(current, replaced, replacement, |folder| &folder.id)
+}
+
+fn merge_by_id<T>(current: Vec<T>, replaced: &[T], replacement: Vec<T>, id: fn(&T) -> &str) -> Vec<T> {
+    let newer: Vec<T> = current
+        .into_iter()
+        .filter(|x| !replaced.iter().any(|y| id(y) == id(x)))
+        .filter(|x| !replacement.iter().any(|y| id(y) == id(x)))
+        .collect();
+
+    replacement.into_iter().chain(newer).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(text: &str) -> HistoryItem {
+        ClipboardEntry::from(text.to_string()).into()
+    }
+
+    fn ids<T>(items: &[T], id: impl Fn(&T) -> &str) -> Vec<String> {
+        items.iter().map(|x| id(x).to_string()).collect()
+    }
+
+    #[test]
+    fn undoing_a_delete_keeps_newer_copies_in_front() {
+        let (a, b, c) = (item("a"), item("b"), item("c"));
+        let before = vec![a.clone(), b.clone(), c.clone()];
+        let after = vec![a.clone(), c.clone()];
+        let d = item("d");
+        let current = vec![d.clone(), a.clone(), c.clone()];
+
+        let merged = merge_history(current, &after, before);
+
+        assert_eq!(ids(&merged, |x| &x.id), ids(&[d, a, b, c], |x| &x.id));
+    }
+
+    #[test]
+    fn undo_skips_entries_copied_again_since() {
+        let (a, b) = (item("a"), item("b"));
+        let before = vec![a.clone(), b];
+        let after = vec![a.clone()];
+        let copied_again = item("b");
+        let current = vec![copied_again.clone(), a.clone()];
+
+        let merged = merge_history(current, &after, before);
+
+        assert_eq!(ids(&merged, |x| &x.id), ids(&[copied_again, a], |x| &x.id));
+    }
+
+    #[test]
+    fn undoing_a_bookmark_delete_keeps_newer_bookmarks_last() {
+        let (a, b, c) = (Bookmark::new("a".into()), Bookmark::new("b".into()), Bookmark::new("c".into()));
+        let before = vec![a.clone(), b.clone(), c.clone()];
+        let after = vec![a.clone(), c.clone()];
+        let added = Bookmark::new("new".into());
+        let current = vec![a.clone(), c.clone(), added.clone()];
+
+        let merged = merge_bookmarks(current, &after, before);
+
+        assert_eq!(ids(&merged, |x| &x.id), ids(&[a, b, c, added], |x| &x.id));
+    }
+
+    fn folder(id: &str, parent_id: Option<&str>) -> BookmarkFolder {
+        BookmarkFolder {
+            id: id.into(),
+            name: id.to_uppercase(),
+            parent_id: parent_id.map(String::from),
+            path: None,
+        }
+    }
+
+    #[test]
+    fn undoing_a_folder_delete_restores_its_bookmarks_too() {
+        let (work, mail) = (folder("work", None), folder("mail", Some("work")));
+        let filed = Bookmark { folder_id: Some("work".into()), ..Bookmark::new("filed".into()) };
+        let moved_up = Bookmark { folder_id: None, ..filed.clone() };
+        let change = Change::folders("Delete folder", vec![work.clone(), mail.clone()], vec![folder("mail", None)])
+            .with_bookmarks(vec![filed.clone()], vec![moved_up.clone()]);
+        let mut stack = UndoStack::default();
+        stack.record(change);
+
+        let Snapshot::Folders { before, after, bookmarks_before, bookmarks_after } = stack.take_undo().unwrap().snapshot
+        else {
+            panic!("not a folder change");
+        };
+        let folders = merge_folders(vec![folder("mail", None), folder("new", None)], &before, after);
+        let bookmarks = merge_bookmarks(vec![moved_up], &bookmarks_before, bookmarks_after);
+
+        assert_eq!(ids(&folders, |x| &x.id), vec!["work", "mail", "new"]);
+        assert_eq!(folders[1].parent_id.as_deref(), Some("work"));
+        assert_eq!(bookmarks[0].folder_id.as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn folder_only_changes_leave_bookmarks_alone() {
+        let bookmarks = vec![Bookmark::new("a".into()), Bookmark::new("b".into())];
+        let Snapshot::Folders { bookmarks_before, bookmarks_after, .. } =
+            Change::folders("Create folder", vec![], vec![folder("new", None)]).reversed().snapshot
+        else {
+            panic!("not a folder change");
+        };
+
+        let merged = merge_bookmarks(bookmarks.clone(), &bookmarks_before, bookmarks_after);
+
+        assert_eq!(ids(&merged, |x| &x.id), ids(&bookmarks, |x| &x.id));
+    }
+
+    #[test]
+    fn undo_and_redo_move_changes_between_the_stacks() {
+        let mut stack = UndoStack::default();
+        stack.record(Change::history("Delete clipboard item", vec![item("a")], vec![]));
+
+        let undo = stack.take_undo().unwrap();
+        assert!(matches!(&undo.snapshot, Snapshot::History { after, .. } if after.len() == 1));
+        stack.push_redo(undo);
+        let state = stack.state();
+        assert!(!state.can_undo);
+        assert_eq!(state.redo_label.as_deref(), Some("Delete clipboard item"));
+
+        let redo = stack.take_redo().unwrap();
+        assert!(matches!(&redo.snapshot, Snapshot::History { after, .. } if after.is_empty()));
+        stack.push_undo(redo);
+        assert!(stack.state().can_undo);
+        assert!(!stack.state().can_redo);
+    }
+
+    #[test]
+    fn recording_forgets_the_redo_stack_and_old_changes() {
+        let mut stack = UndoStack::default();
+        stack.record(Change::bookmarks("Delete bookmark", vec![], vec![]));
+        let undo = stack.take_undo().unwrap();
+        stack.push_redo(undo);
+
+        for _ in 0..MAX_UNDO_DEPTH + 5 {
+            stack.record(Change::bookmarks("Delete bookmark", vec![], vec![]));
+        }
+
+        assert!(!stack.state().can_redo);
+        assert_eq!(stack.undo.len(), MAX_UNDO_DEPTH);
+    }
+}
@@ -0,0 +1,316 @@
+//! Checks of the settings before they are applied. `set_config` rejects invalid
+//! settings with one error per field; at startup they fall back to their defaults
+//! and the UI is warned.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::Path;
+use tauri_plugin_global_shortcut::Shortcut;
+
+use crate::clipboard_manager::handlers::{config_shortcuts, parse_shortcut};
+use crate::commands::AppConfig;
+
+/// A setting that can't be used. `field` is the setting's name in config.json,
+/// with a dot for nested settings (e.g. `expiry.maxAgeSeconds`).
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Error of `set_config`: invalid settings are listed in `fields`, anything else
+/// (e.g. the file could not be written) only has a message.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigError {
+    pub message: String,
+    pub fields: Vec<FieldError>,
+}
+
+impl ConfigError {
+    pub fn invalid(fields: Vec<FieldError>) -> Self {
+        ConfigError {
+            message: "Some settings are invalid".into(),
+            fields,
+        }
+    }
+}
+
+impl From<String> for ConfigError {
+    fn from(message: String) -> Self {
+        ConfigError { message, fields: vec![] }
+    }
+}
+
+/// Problems found while loading the settings, shown by the UI once it is up.
+#[derive(Default)]
+pub struct StartupWarnings(pub Vec<String>);
+
+pub fn validate_config(config: &AppConfig) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    if config.max_items == 0 {
+        errors.push(FieldError::new("maxItems", "Keep at least 1 item"));
+    }
+    for rule in &config.sensitive_filter.rules {
+        if let Some(e) = rule.pattern.error() {
+            errors.push(FieldError::new("sensitiveFilter.rules", format!("Invalid pattern in rule {}: {}", rule.name, e)));
+        }
+    }
+    for rule in &config.expiry.rules {
+        if let Some(e) = rule.pattern.as_ref().and_then(|pattern| pattern.error()) {
+            errors.push(FieldError::new("expiry.rules", format!("Invalid pattern in rule {}: {}", rule.name, e)));
+        }
+    }
+    if config.expiry.max_age_seconds == Some(0) {
+        errors.push(FieldError::new("expiry.maxAgeSeconds", "Must be at least 1 second"));
+    }
+    if let Some(path) = &config.snippet_dir.path {
+        if !Path::new(path).is_dir() {
+            errors.push(FieldError::new("snippetDir.path", format!("{} is not a directory", path)));
+        }
+    }
+
+    let mut seen: Vec<(Shortcut, &str)> = Vec::new();
+    for (field, name, key) in config_shortcuts(config) {
+        match parse_shortcut(key) {
+            Ok(shortcut) => match seen.iter().find(|(other, _)| *other == shortcut) {
+                Some((_, other_name)) => {
+                    errors.push(FieldError::new(field, format!("{} is already the {} shortcut", key, other_name)))
+                }
+                None => seen.push((shortcut, name)),
+            },
+            Err(e) => errors.push(FieldError::new(field, e)),
+        }
+    }
+
+    errors
+}
+
+/// Puts the defaults back for the settings that fail validation (the whole
+/// top-level setting for nested ones). Returns the errors, to warn about them.
+pub fn sanitize_config(config: AppConfig) -> (AppConfig, Vec<FieldError>) {
+    let defaults = serde_json::to_value(AppConfig::default()).unwrap_or_default();
+    let Ok(Value::Object(mut fields)) = serde_json::to_value(&config) else {
+        return (AppConfig::default(), validate_config(&config));
+    };
+    let is_default = |fields: &Map<String, Value>, name: &str| fields.get(name) == defaults.get(name);
+
+    // A default can clash with a shortcut that was kept (e.g. the default open
+    // shortcut with the same clear shortcut), so validate again until it settles
+    let mut config = config;
+    let mut errors: Vec<FieldError> = Vec::new();
+    loop {
+        let mut reset: Vec<String> = Vec::new();
+        for error in validate_config(&config) {
+            let name = top_level(&error.field);
+            if !is_default(&fields, name) {
+                reset.push(name.to_string());
+                errors.push(error);
+            } else if let Some((other, key, default_name)) = clashing_shortcut(&config, name) {
+                // Already the default: the kept shortcut taking it gets its default too
+                if !is_default(&fields, other) {
+                    reset.push(other.to_string());
+                    errors.push(FieldError::new(other, format!("{} is the default {} shortcut", key, default_name)));
+                }
+            }
+        }
+        if reset.is_empty() {
+            break;
+        }
+        for name in reset {
+            fields.insert(name.clone(), defaults.get(&name).cloned().unwrap_or_default());
+        }
+        config = from_fields(&fields);
+    }
+
+    (config, errors)
+}
+
+/// The other shortcut setting holding the same shortcut as `field`, with that
+/// shortcut and the name of `field`'s shortcut.
+fn clashing_shortcut<'a>(config: &'a AppConfig, field: &str) -> Option<(&'static str, &'a str, &'static str)> {
+    let shortcuts = config_shortcuts(config);
+    let (_, name, key) = shortcuts.iter().find(|(f, _, _)| *f == field)?;
+    let shortcut = parse_shortcut(key).ok()?;
+
+    shortcuts
+        .iter()
+        .find(|(f, _, other)| *f != field && parse_shortcut(other).ok() == Some(shortcut))
+        .map(|(other, _, key)| (*other, *key, *name))
+}
+
+fn top_level(field: &str) -> &str {
+    field.split('.').next().unwrap_or_default()
+}
+
+fn from_fields(fields: &Map<String, Value>) -> AppConfig {
+    serde_json::from_value(Value::Object(fields.clone())).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard_manager::expiry::ExpiryRule;
+    use crate::clipboard_manager::filter::{FilterAction, Pattern, RegexRule};
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(validate_config(&AppConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn rejects_out_of_range_numbers() {
+        let mut config = AppConfig { max_items: 0, ..Default::default() };
+        config.expiry.max_age_seconds = Some(0);
+
+        assert_eq!(fields(&validate_config(&config)), vec!["maxItems", "expiry.maxAgeSeconds"]);
+    }
+
+    #[test]
+    fn rejects_invalid_rule_patterns() {
+        let mut config = AppConfig::default();
+        config.sensitive_filter.rules.push(RegexRule {
+            name: "Broken".into(),
+            pattern: Pattern::new("[a-"),
+            action: FilterAction::Drop,
+        });
+        config.expiry.rules.push(ExpiryRule {
+            pattern: Some(Pattern::new("(")),
+            ..serde_json::from_str(r#"{ "name": "Broken too", "maxAgeSeconds": 60 }"#).unwrap()
+        });
+
+        let errors = validate_config(&config);
+
+        assert_eq!(fields(&errors), vec!["sensitiveFilter.rules", "expiry.rules"]);
+        assert!(errors[0].message.starts_with("Invalid pattern in rule Broken:"));
+    }
+
+    #[test]
+    fn snippet_dir_must_be_a_directory() {
+        let mut config = AppConfig::default();
+        config.snippet_dir.path = Some(std::env::temp_dir().to_string_lossy().to_string());
+        assert!(validate_config(&config).is_empty());
+
+        config.snippet_dir.path = Some(std::env::temp_dir().join("clipbored-missing-dir").to_string_lossy().to_string());
+        assert_eq!(fields(&validate_config(&config)), vec!["snippetDir.path"]);
+    }
+
+    #[test]
+    fn rejects_invalid_shortcuts() {
+        let config = AppConfig {
+            open_shortcut: "Ctrl+Nope".into(),
+            queue_shortcut: Some("Meta+Q".into()),
+            ..Default::default()
+        };
+
+        assert_eq!(fields(&validate_config(&config)), vec!["openShortcut"]);
+    }
+
+    #[test]
+    fn rejects_shortcuts_used_twice() {
+        let config = AppConfig {
+            clear_shortcut: Some("Super+Ctrl+V".into()),
+            paste_next_shortcut: Some("Ctrl+Super+B".into()),
+            ..Default::default()
+        };
+
+        let errors = validate_config(&config);
+
+        assert_eq!(fields(&errors), vec!["clearShortcut", "pasteNextShortcut"]);
+        assert_eq!(errors[0].message, "Super+Ctrl+V is already the open shortcut");
+    }
+
+    #[test]
+    fn sanitizing_resets_only_the_invalid_settings() {
+        let mut config = AppConfig {
+            max_items: 0,
+            persist_history: false,
+            ..Default::default()
+        };
+        config.sensitive_filter.enabled = false;
+        config.sensitive_filter.rules.push(RegexRule {
+            name: "Broken".into(),
+            pattern: Pattern::new("[a-"),
+            action: FilterAction::Drop,
+        });
+
+        let (config, errors) = sanitize_config(config);
+
+        assert_eq!(fields(&errors), vec!["maxItems", "sensitiveFilter.rules"]);
+        assert_eq!(config.max_items, AppConfig::default().max_items);
+        assert!(!config.persist_history);
+        // Nested settings are reset as a whole
+        assert!(config.sensitive_filter.enabled);
+        assert!(config.sensitive_filter.rules.is_empty());
+    }
+
+    #[test]
+    fn sanitizing_checks_defaults_against_the_kept_shortcuts() {
+        let config = AppConfig {
+            open_shortcut: "Ctrl+Nope".into(),
+            clear_shortcut: Some("Ctrl+Super+V".into()),
+            ..Default::default()
+        };
+
+        let (config, errors) = sanitize_config(config);
+
+        assert_eq!(fields(&errors), vec!["openShortcut", "clearShortcut"]);
+        assert_eq!(config.open_shortcut, AppConfig::default().open_shortcut);
+        assert_eq!(config.clear_shortcut, None);
+        assert!(validate_config(&config).is_empty());
+    }
+
+    #[test]
+    fn sanitizing_resets_kept_shortcuts_that_take_a_default() {
+        // The default bookmark shortcut is taken by the open shortcut, which is valid on its own
+        let config = AppConfig {
+            open_shortcut: "Ctrl+Super+B".into(),
+            bookmark_shortcut: "Ctrl+Nope".into(),
+            queue_shortcut: Some("Ctrl+Alt+Q".into()),
+            ..Default::default()
+        };
+
+        let (config, errors) = sanitize_config(config);
+
+        assert_eq!(fields(&errors), vec!["bookmarkShortcut", "openShortcut"]);
+        assert_eq!(errors[1].message, "Ctrl+Super+B is the default bookmark shortcut");
+        assert!(validate_config(&config).is_empty());
+        assert_eq!(config.open_shortcut, AppConfig::default().open_shortcut);
+        assert_eq!(config.bookmark_shortcut, AppConfig::default().bookmark_shortcut);
+        assert_eq!(config.queue_shortcut.as_deref(), Some("Ctrl+Alt+Q"));
+    }
+
+    #[test]
+    fn sanitizing_follows_chains_of_clashing_defaults() {
+        // Resetting the open shortcut frees the bookmark default, but takes the clear one
+        let config = AppConfig {
+            open_shortcut: "Ctrl+Super+B".into(),
+            bookmark_shortcut: "Ctrl+Nope".into(),
+            clear_shortcut: Some("Ctrl+Super+V".into()),
+            paste_next_shortcut: Some("Ctrl+Alt+N".into()),
+            ..Default::default()
+        };
+
+        let (config, errors) = sanitize_config(config);
+
+        assert_eq!(fields(&errors), vec!["bookmarkShortcut", "openShortcut", "clearShortcut"]);
+        assert!(validate_config(&config).is_empty());
+        assert_eq!(config.clear_shortcut, None);
+        assert_eq!(config.paste_next_shortcut.as_deref(), Some("Ctrl+Alt+N"));
+    }
+}
@@ -1,10 +1,178 @@
 export interface AppConfig {
+    version: number;
     maxItems: number;
     openShortcut: string;
     bookmarkShortcut: string;
     startMinimized: boolean;
+    persistHistory: boolean;
+    sensitiveFilter: SensitiveFilterConfig;
+    expiry: ExpiryConfig;
+    clearShortcut: string | null;
+    pasteQueueOrder: 'fifo' | 'lifo';
+    queueShortcut: string | null;
+    pasteNextShortcut: string | null;
+    traySort: SortMode;
+    expansion: ExpansionConfig;
+    snippetDir: SnippetDirConfig;
+}
+
+/** A setting rejected by `set_config`, named like in AppConfig (`expiry.maxAgeSeconds` when nested) */
+export interface FieldError {
+    field: string;
+    message: string;
+}
+
+export interface ConfigError {
+    message: string;
+    fields: FieldError[];
+}
+
+export interface SnippetDirConfig {
+    path: string | null;
+    mode: 'readOnly' | 'writeThrough';
+}
+
+export interface ExpansionConfig {
+    paused: boolean;
+    trigger: 'immediate' | 'space' | 'tab' | 'enter';
+    disabledApps: string[];
+    enabledApps: string[];
+}
+
+export type SortMode = 'default' | 'frecency';
+
+export type Transform =
+    | 'plain' | 'trim' | 'upper' | 'lower' | 'title' | 'collapseWhitespace'
+    | 'urlEncode' | 'urlDecode' | 'base64Encode' | 'base64Decode'
+    | 'jsonPretty' | 'jsonMinify' | 'shellEscape' | 'sqlEscape';
+
+export interface FieldsRequest {
+    id: string;
+    fields: string[];
+    transform: Transform | null;
+}
+
+export interface PasteQueueState {
+    active: boolean;
+    length: number;
+}
+
+export interface ClearOptions {
+    keepPinned?: boolean;
+    keepLast?: number | null;
+    olderThanSeconds?: number | null;
+}
+
+export interface ExpiryRule {
+    name: string;
+    kind: ClipboardEntry['kind'] | null;
+    source: string | null;
+    pattern: string | null;
+    maxAgeSeconds: number | null;
+}
+
+export interface ExpiryConfig {
+    maxAgeSeconds: number | null;
+    rules: ExpiryRule[];
+}
+
+export type FilterAction =
+    | { type: 'drop' }
+    | { type: 'mask' }
+    | { type: 'expire'; seconds: number };
+
+export interface SensitiveFilterConfig {
+    enabled: boolean;
+    detectors: { detector: 'creditCard' | 'awsKey' | 'githubToken' | 'jwt' | 'privateKey'; action: FilterAction }[];
+    rules: { name: string; pattern: string; action: FilterAction }[];
 }
 
 export interface Bookmark {
+    id: string;
     content: string;
-}
\ No newline at end of file
+    historyId: string | null;
+    title: string | null;
+    description: string | null;
+    tags: string[];
+    folderId: string | null;
+    hotkey: string | null;
+    abbreviation: string | null;
+    /** Set for snippets from the snippet directory */
+    file: string | null;
+    pasteCount: number;
+    lastPastedAt: number | null;
+}
+
+export type BookmarkFormat = 'json' | 'csv' | 'directory' | 'espanso';
+
+export type DuplicatePolicy = 'skip' | 'replace' | 'keepBoth';
+
+export interface ImportReport {
+    dryRun: boolean;
+    added: number;
+    replaced: number;
+    skipped: number;
+    foldersCreated: number;
+    unsupported: string[];
+}
+
+export interface BookmarkFolder {
+    id: string;
+    name: string;
+    parentId: string | null;
+    /** Set for folders from the snippet directory */
+    path: string | null;
+}
+
+export type ClipboardEntry =
+    | { kind: 'text'; text: string }
+    | { kind: 'html'; html: string; altText: string | null }
+    | { kind: 'image'; hash: string; width: number; height: number }
+    | { kind: 'fileList'; paths: string[] };
+
+export interface HistoryItem {
+    id: string;
+    entry: ClipboardEntry;
+    firstCopiedAt: number;
+    lastCopiedAt: number;
+    copyCount: number;
+    size: { bytes: number; chars: number };
+    source: string | null;
+    masked: boolean;
+    expiresAt: number | null;
+    pinned: boolean;
+    pasteCount: number;
+    lastPastedAt: number | null;
+}
+
+export interface SearchFilters {
+    kinds?: ClipboardEntry['kind'][];
+    from?: number;
+    to?: number;
+    bookmarkedOnly?: boolean;
+    limit?: number;
+}
+
+export interface SearchResult {
+    id: string;
+    source: 'history' | 'bookmark';
+    /** The text that matched; `highlights` index into it */
+    text: string;
+    field: 'content' | 'title' | 'description' | 'tag';
+    score: number;
+    highlights: [number, number][];
+    bookmarked: boolean;
+}
+
+export interface StoreStatus {
+    encrypted: boolean;
+    unlocked: boolean;
+    keySource: { type: 'passphrase' } | { type: 'keyFile'; path: string } | null;
+}
+
+export interface UndoState {
+    canUndo: boolean;
+    canRedo: boolean;
+    undoLabel: string | null;
+    redoLabel: string | null;
+}
@@ -4,9 +4,10 @@ import { invoke } from "@tauri-apps/api/core";
 import { listen } from '@tauri-apps/api/event';
 import { ButtonModule } from 'primeng/button';
 import { FieldsetModule } from 'primeng/fieldset';
+import { InputTextModule } from 'primeng/inputtext';
 import { ListboxModule } from 'primeng/listbox';
 import { PanelModule } from 'primeng/panel';
-import { Bookmark } from '../app-config.model';
+import { Bookmark, BookmarkFolder, BookmarkFormat, DuplicatePolicy, FieldsRequest, ImportReport, SortMode } from '../app-config.model';
 
 @Component({
     selector: 'app-bookmark-list',
@@ -16,13 +17,28 @@ import { Bookmark } from '../app-config.model';
         FieldsetModule,
         PanelModule,
         ListboxModule,
-        ButtonModule
+        ButtonModule,
+        InputTextModule
     ],
     templateUrl: './bookmark-list.component.html',
     styleUrl: './bookmark-list.component.css'
 })
 export class BookmarkListComponent implements OnInit {
     bookmarks: WritableSignal<Bookmark[]> = signal([]);
+    folders = signal<BookmarkFolder[]>([]);
+    // Folders from the snippet directory mirror its subdirectories and can't be changed here
+    personalFolders = computed(() => this.folders().filter((folder) => !folder.path));
+    sort: SortMode = 'default';
+    newFolderName = '';
+    transferPath = '';
+    transferFormat: BookmarkFormat = 'json';
+    duplicatePolicy: DuplicatePolicy = 'skip';
+    importReport = signal<ImportReport | null>(null);
+    transferError = signal<string | null>(null);
+    fieldsRequest = signal<FieldsRequest | null>(null);
+    fieldValues: Record<string, string> = {};
+    editing = signal<{ id: string; content: string; title: string; description: string; tags: string; hotkey: string; abbreviation: string } | null>(null);
+    detailsError = signal<string | null>(null);
 
     constructor() {
         
@@ -32,32 +48,172 @@ export class BookmarkListComponent implements OnInit {
         this.loadBookmarks();
 
         listen("bookmarks-updated", () => this.loadBookmarks());
+
+        // A snippet with {prompt:...} fields was pasted, from the tray or from here
+        listen<FieldsRequest>("snippet-fields-requested", (event) => {
+            this.fieldValues = {};
+            this.fieldsRequest.set(event.payload);
+        });
     }
 
     async loadBookmarks() {
-        const loadedBookmarks = await invoke<Bookmark[]>('get_bookmarks');
-        if (loadedBookmarks && loadedBookmarks.length > 0) {
-            this.bookmarks.set(loadedBookmarks);
-        }
+        const loadedBookmarks = await invoke<Bookmark[]>('get_bookmarks', { sort: this.sort });
+        // Undo can bring the list back to empty, so always replace it
+        this.bookmarks.set(loadedBookmarks ?? []);
+        this.folders.set(await invoke<BookmarkFolder[]>('get_folders'));
 
         console.log("Bookmarks loaded", this.bookmarks());
     }
 
-    removeBookmark(item: string): void {
-        let index;
-        this.bookmarks.update((bookmarks) => bookmarks.filter((txt, idx) => {
-            if (txt.content === item) {
-                index = idx;
-            }
-            return txt.content !== item
-        }));
-        invoke('remove_bookmark', { index }).then(() => {
+    setSort(sort: SortMode) {
+        this.sort = sort;
+        this.loadBookmarks();
+    }
+
+    removeBookmark(id: string): void {
+        this.bookmarks.update((bookmarks) => bookmarks.filter((bookmark) => bookmark.id !== id));
+        invoke('remove_bookmark', { id }).then(() => {
             console.log("Bookmark removed");
+        }).catch((error) => {
+            // e.g. a read-only snippet from the snippet directory
+            this.transferError.set(`${error}`);
+            this.loadBookmarks();
+        });
+    }
+
+    async pasteBookmark(id: string) {
+        await invoke('hide_window');
+        invoke('paste_item', { id, transform: null, fields: null });
+    }
+
+    async pasteWithFields() {
+        const request = this.fieldsRequest();
+        if (!request) {
+            return;
+        }
+        this.fieldsRequest.set(null);
+        await invoke('hide_window');
+        invoke('paste_item', { id: request.id, transform: request.transform, fields: this.fieldValues });
+    }
+
+    editBookmark(bookmark: Bookmark) {
+        this.editing.set({
+            id: bookmark.id,
+            content: bookmark.content,
+            title: bookmark.title ?? '',
+            description: bookmark.description ?? '',
+            tags: bookmark.tags.join(', '),
+            hotkey: bookmark.hotkey ?? '',
+            abbreviation: bookmark.abbreviation ?? ''
+        });
+        this.detailsError.set(null);
+    }
+
+    async saveDetails() {
+        const details = this.editing();
+        if (!details) {
+            return;
+        }
+        const bookmark = this.bookmarks().find((b) => b.id === details.id);
+        // Only the content of a snippet from the snippet directory can be edited (its file)
+        if (bookmark?.file) {
+            try {
+                await invoke('update_bookmark', { id: details.id, content: details.content });
+            } catch (error) {
+                this.detailsError.set(`${error}`);
+                return;
+            }
+            this.editing.set(null);
+            this.detailsError.set(null);
+            return;
+        }
+        // A hotkey or abbreviation that is taken or invalid keeps the form open with the reason
+        try {
+            if (details.hotkey.trim() !== (bookmark?.hotkey ?? '')) {
+                await invoke('set_bookmark_hotkey', { id: details.id, hotkey: details.hotkey || null });
+            }
+            if (details.abbreviation.trim() !== (bookmark?.abbreviation ?? '')) {
+                await invoke('set_bookmark_abbreviation', { id: details.id, abbreviation: details.abbreviation || null });
+            }
+        } catch (error) {
+            this.detailsError.set(`${error}`);
+            return;
+        }
+        this.editing.set(null);
+        this.detailsError.set(null);
+        invoke('update_bookmark', { id: details.id, content: details.content });
+        invoke('set_bookmark_details', {
+            id: details.id,
+            title: details.title,
+            description: details.description,
+            tags: details.tags.split(',')
         });
     }
 
-    toggleBookmark(item: string) {
-        invoke('toggle_bookmark', { content: item });
+    moveBookmark(bookmark: Bookmark, offset: number) {
+        const index = this.bookmarks().findIndex((b) => b.id === bookmark.id) + offset;
+        if (index >= 0) {
+            invoke('move_bookmark', { id: bookmark.id, index });
+        }
+    }
+
+    importBookmarks(dryRun: boolean) {
+        this.transferError.set(null);
+        invoke<ImportReport>('import_bookmarks', {
+            path: this.transferPath,
+            format: this.transferFormat,
+            duplicates: this.duplicatePolicy,
+            dryRun
+        }).then((report) => this.importReport.set(report))
+            .catch((error) => this.transferError.set(`${error}`));
+    }
+
+    exportBookmarks() {
+        this.transferError.set(null);
+        this.importReport.set(null);
+        invoke('export_bookmarks', { path: this.transferPath, format: this.transferFormat })
+            .catch((error) => this.transferError.set(`${error}`));
+    }
+
+    /** Folder name with its parents, e.g. "Work / SQL" */
+    folderPath(folder: BookmarkFolder): string {
+        const parent = this.folders().find((f) => f.id === folder.parentId);
+        return parent ? `${this.folderPath(parent)} / ${folder.name}` : folder.name;
+    }
+
+    createFolder() {
+        const name = this.newFolderName.trim();
+        if (!name) {
+            return;
+        }
+        this.newFolderName = '';
+        invoke('create_folder', { name, parentId: null });
+    }
+
+    renameFolder(folder: BookmarkFolder) {
+        const name = prompt('Folder name', folder.name)?.trim();
+        if (name) {
+            invoke('rename_folder', { id: folder.id, name });
+        }
+    }
+
+    moveFolder(folder: BookmarkFolder, parentId: string) {
+        invoke('move_folder', { id: folder.id, parentId: parentId || null }).catch((error) => {
+            alert(error);
+            this.loadBookmarks();
+        });
+    }
+
+    deleteFolder(folder: BookmarkFolder) {
+        invoke('delete_folder', { id: folder.id });
+    }
+
+    setBookmarkFolder(bookmark: Bookmark, folderId: string) {
+        invoke('set_bookmark_folder', { id: bookmark.id, folderId: folderId || null });
+    }
+
+    toggleBookmark(id: string) {
+        invoke('toggle_bookmark', { id });
     }
 
 }
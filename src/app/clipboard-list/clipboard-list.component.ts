@@ -4,6 +4,7 @@ import { listen } from '@tauri-apps/api/event'; // Import listen
 import { ButtonModule } from 'primeng/button';
 import { ListboxModule } from 'primeng/listbox';
 import { PanelModule } from 'primeng/panel';
+import { ClearOptions, ClipboardEntry, HistoryItem, PasteQueueState, Transform, UndoState } from '../app-config.model';
 
 @Component({
     selector: 'app-clipboard-list',
@@ -17,11 +18,28 @@ import { PanelModule } from 'primeng/panel';
 })
 export class ClipboardListComponent implements OnInit{
     
-    clipboardItems = signal([
-        'Sample clipboard item 1',
-        'Sample clipboard item 2', 
-        'Sample clipboard item 3'
-    ]);
+    clipboardItems = signal<HistoryItem[]>([]);
+    suppressedNotice = signal<string | null>(null);
+    undoState = signal<UndoState | null>(null);
+    pasteQueue = signal<PasteQueueState | null>(null);
+    pasteTransform = signal<Transform | null>(null);
+
+    TRANSFORMS: { value: Transform; label: string }[] = [
+        { value: 'plain', label: 'Plain text' },
+        { value: 'trim', label: 'Trim' },
+        { value: 'upper', label: 'UPPER CASE' },
+        { value: 'lower', label: 'lower case' },
+        { value: 'title', label: 'Title Case' },
+        { value: 'collapseWhitespace', label: 'Collapse whitespace' },
+        { value: 'urlEncode', label: 'URL encode' },
+        { value: 'urlDecode', label: 'URL decode' },
+        { value: 'base64Encode', label: 'Base64 encode' },
+        { value: 'base64Decode', label: 'Base64 decode' },
+        { value: 'jsonPretty', label: 'JSON pretty-print' },
+        { value: 'jsonMinify', label: 'JSON minify' },
+        { value: 'shellEscape', label: 'Escape for shell' },
+        { value: 'sqlEscape', label: 'Escape for SQL' },
+    ];
 
     ngOnInit() {
         this.loadClipboardItems();
@@ -29,20 +47,91 @@ export class ClipboardListComponent implements OnInit{
         // Listen for the clipboard-updated event
         listen('clipboard-updated', () => {
             this.loadClipboardItems(); // Reload items on event
+            // New copies may have been queued
+            invoke<PasteQueueState>('get_paste_queue').then((state) => this.pasteQueue.set(state));
+        })
+
+        invoke<UndoState>('get_undo_state').then((state) => this.undoState.set(state));
+        listen<UndoState>('undo-available', (event) => {
+            this.undoState.set(event.payload);
+        })
+
+        invoke<PasteQueueState>('get_paste_queue').then((state) => this.pasteQueue.set(state));
+        listen<PasteQueueState>('paste-queue-updated', (event) => {
+            this.pasteQueue.set(event.payload);
+        })
+
+        // The backend skipped a copied entry (password manager secret, API key, ...)
+        listen<string>('clipboard-suppressed', (event) => {
+            const when = new Date().toLocaleTimeString();
+            this.suppressedNotice.set(`A copied item was not saved at ${when} (${event.payload}).`);
         })
     }
 
     async loadClipboardItems() {
-        const items = await invoke<string[]>('get_clipboard_items');
+        const items = await invoke<HistoryItem[]>('get_clipboard_items');
         this.clipboardItems.set(items);
     }
 
-    toggleBookmark(item: string) {
-        invoke('toggle_bookmark', { content: item });
+    entryLabel(item: ClipboardEntry): string {
+        switch (item.kind) {
+            case 'text':
+                return item.text;
+            case 'html':
+                return item.altText ?? item.html;
+            case 'image':
+                return `[Image ${item.width}x${item.height}]`;
+            case 'fileList':
+                return item.paths.join('\n');
+        }
+    }
+
+    itemDetails(item: HistoryItem): string {
+        const copied = new Date(item.lastCopiedAt).toLocaleString();
+        const times = item.copyCount > 1 ? ` · copied ${item.copyCount} times` : '';
+        const source = item.source ? ` · from ${item.source}` : '';
+        return `${copied}${times}${source}`;
+    }
+
+    toggleBookmark(item: HistoryItem) {
+        if (item.entry.kind === 'image') {
+            return;
+        }
+        invoke('toggle_bookmark', { id: item.id });
+    }
+
+    togglePin(item: HistoryItem) {
+        invoke('toggle_pin', { id: item.id });
+    }
+
+    clearHistory(options: ClearOptions = { keepPinned: true }) {
+        invoke('clear_history', { options });
+    }
+
+    setPasteTransform(value: string) {
+        this.pasteTransform.set(value ? value as Transform : null);
+    }
+
+    async pasteItem(item: HistoryItem) {
+        // Paste into the window that was focused before this one
+        await invoke('hide_window');
+        invoke('paste_item', { id: item.id, transform: this.pasteTransform() });
+    }
+
+    togglePasteQueue() {
+        invoke('toggle_paste_queue');
+    }
+
+    undo() {
+        invoke<UndoState>('undo').then((state) => this.undoState.set(state));
+    }
+
+    redo() {
+        invoke<UndoState>('redo').then((state) => this.undoState.set(state));
     }
 
-    deleteItem(item: string) {
-        invoke('delete_clipboard_item', { item }).then(() => {
+    deleteItem(item: HistoryItem) {
+        invoke('delete_clipboard_item', { id: item.id }).then(() => {
             this.loadClipboardItems();
         });
     }
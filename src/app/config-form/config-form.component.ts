@@ -7,6 +7,7 @@ import {
     Validators,
 } from '@angular/forms';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { ButtonModule } from 'primeng/button';
 import { CardModule } from 'primeng/card';
 import { CheckboxModule } from 'primeng/checkbox';
@@ -17,7 +18,7 @@ import { InputSwitchModule } from 'primeng/inputswitch';
 import { InputTextModule } from 'primeng/inputtext';
 import { PanelModule } from 'primeng/panel';
 import { ToastModule } from 'primeng/toast';
-import { AppConfig } from '../app-config.model';
+import { AppConfig, ConfigError, StoreStatus } from '../app-config.model';
 
 import { MessageService } from 'primeng/api';
 import { BookmarkListComponent } from '../bookmark-list/bookmark-list.component';
@@ -49,16 +50,31 @@ import { ClipboardListComponent } from "../clipboard-list/clipboard-list.compone
 export class ConfigFormComponent {
     configForm: FormGroup;
     config = signal<AppConfig>({
+        version: 1,
         maxItems: 10,
         openShortcut: 'Ctrl+Super+V',
         bookmarkShortcut: 'Ctrl+Super+B',
-        startMinimized: false
+        startMinimized: false,
+        persistHistory: true,
+        sensitiveFilter: { enabled: true, detectors: [], rules: [] },
+        expiry: { maxAgeSeconds: null, rules: [] },
+        clearShortcut: null,
+        pasteQueueOrder: 'fifo',
+        queueShortcut: null,
+        pasteNextShortcut: null,
+        traySort: 'default',
+        expansion: { paused: false, trigger: 'immediate', disabledApps: [], enabledApps: [] },
+        snippetDir: { path: null, mode: 'readOnly' }
     });
     tempShortcutValue = '';
+    storeStatus = signal<StoreStatus | null>(null);
+    passphrase = '';
 
     isSettingsOpen = false;
 
     MODIFIERS = ['Ctrl', 'Shift', 'Alt', 'Super'];
+    /** Shortcuts that can be unset with Backspace or Delete */
+    OPTIONAL_SHORTCUTS = ['clearShortcut', 'queueShortcut', 'pasteNextShortcut'];
     SUPER_MODIFIERS = ['Cmd', 'CmdLeft', 'CmdRight', 'Super', 'Meta', 'MetaLeft', 'MetaRight'];
     DENIED_KEYS = [
         'Delete', 'Backspace', 'Insert', 'CapsLock', 'Escape', 'NumLock', 'Home',
@@ -72,9 +88,31 @@ export class ConfigFormComponent {
             openShortcut: ['Ctrl+Super+V', Validators.required],
             bookmarkShortcut: ['Ctrl+Super+B', Validators.required],
             startMinimized: [false],
+            persistHistory: [true],
+            clearShortcut: [null as string | null],
+            pasteQueueOrder: ['fifo'],
+            queueShortcut: [null as string | null],
+            pasteNextShortcut: [null as string | null],
+            traySort: ['default'],
+            expiry: this.fb.group({
+                maxAgeSeconds: [null as number | null, [Validators.min(1)]],
+            }),
+            // The app lists are edited as comma-separated text
+            expansion: this.fb.group({
+                paused: [false],
+                trigger: ['immediate'],
+                disabledApps: [[] as string[] | string],
+                enabledApps: [[] as string[] | string],
+            }),
+            snippetDir: this.fb.group({
+                path: [null as string | null],
+                mode: ['readOnly'],
+            }),
         });
 
         this.loadConfig();
+        this.loadStoreStatus();
+        this.showStartupWarnings();
 
         effect(() => {
             const currentConfig = this.config();
@@ -82,8 +120,31 @@ export class ConfigFormComponent {
         });
 
         this.configForm.valueChanges.subscribe(values => {
-            this.config.set(values);
+            // Keep the settings that have no form control (e.g. sensitiveFilter)
+            this.config.update(config => ({
+                ...config,
+                ...values,
+                clearShortcut: values.clearShortcut || null,
+                queueShortcut: values.queueShortcut || null,
+                pasteNextShortcut: values.pasteNextShortcut || null,
+                expiry: { ...config.expiry, ...values.expiry },
+                expansion: {
+                    ...config.expansion,
+                    ...values.expansion,
+                    disabledApps: this.toList(values.expansion.disabledApps),
+                    enabledApps: this.toList(values.expansion.enabledApps)
+                },
+                snippetDir: { ...values.snippetDir, path: values.snippetDir.path?.trim() || null }
+            }));
         });
+
+        // Text expansion can be paused from the tray
+        listen('config-updated', () => this.loadConfig());
+    }
+
+    private toList(value: string[] | string): string[] {
+        const items = Array.isArray(value) ? value : value.split(',');
+        return items.map((item) => item.trim()).filter((item) => item !== '');
     }
 
     async loadConfig() {
@@ -93,6 +154,43 @@ export class ConfigFormComponent {
         }
     }
 
+    /** Settings from config.json that were replaced by their defaults at startup */
+    async showStartupWarnings() {
+        const warnings = await invoke<string[]>('get_startup_warnings');
+        warnings.forEach((warning) => {
+            this.messageService.add({ severity: 'warn', summary: 'Settings', detail: warning, sticky: true });
+        });
+    }
+
+    async loadStoreStatus() {
+        this.storeStatus.set(await invoke<StoreStatus>('get_store_status'));
+    }
+
+    unlockStore(): void {
+        invoke('unlock_store', { passphrase: this.passphrase }).then(() => {
+            this.passphrase = '';
+            this.loadStoreStatus();
+        }).catch((error) => {
+            this.messageService.add({ severity: 'error', summary: 'Error', detail: `${error}`, life: 6000 });
+        });
+    }
+
+    setStoreEncryption(enabled: boolean): void {
+        const passphrase = enabled ? this.passphrase : null;
+        invoke<StoreStatus>('set_store_encryption', { passphrase }).then((status) => {
+            this.passphrase = '';
+            this.storeStatus.set(status);
+            this.messageService.add({
+                severity: 'success',
+                summary: 'Success',
+                detail: enabled ? 'Bookmarks and history are now encrypted.' : 'Encryption disabled.',
+                life: 4000
+            });
+        }).catch((error) => {
+            this.messageService.add({ severity: 'error', summary: 'Error', detail: `${error}`, life: 6000 });
+        });
+    }
+
     onFocus(event: FocusEvent) {
         const target = event.target as HTMLInputElement;
         this.tempShortcutValue = target.value;
@@ -102,13 +200,20 @@ export class ConfigFormComponent {
     onBlur(event: FocusEvent) {
         const target = event.target as HTMLInputElement;
         if (target.value === '') {
-            const controlName = target.id === 'openShortcut' ? 'openShortcut' : 'bookmarkShortcut';
-            this.configForm.get(controlName)?.setValue(this.tempShortcutValue);
+            this.configForm.get(target.id)?.setValue(this.tempShortcutValue);
         }
     }
 
     onKeyDown(event: KeyboardEvent) {
         console.log("KeyDown: ", event.code);
+        const target = event.target as HTMLInputElement;
+
+        if (this.OPTIONAL_SHORTCUTS.includes(target.id) && ['Backspace', 'Delete'].includes(event.code)) {
+            event.preventDefault();
+            this.tempShortcutValue = '';
+            this.configForm.get(target.id)?.setValue(null);
+            return;
+        }
 
         if (this.DENIED_KEYS.includes(event.code)) {
             this.keyUp(event);
@@ -117,7 +222,6 @@ export class ConfigFormComponent {
         if (event.key !== 'Shift') {
             event.preventDefault();
         }
-        const target = event.target as HTMLInputElement;
         let key = event.key;
         if (key === ' ') {
             key = 'Space';
@@ -149,14 +253,14 @@ export class ConfigFormComponent {
         const shortcutString = pressedKeys.join('+');
         target.value = shortcutString;
 
-        const controlName = target.id === 'openShortcut' ? 'openShortcut' : 'bookmarkShortcut';
-        this.configForm.get(controlName)?.setValue(shortcutString);
+        this.configForm.get(target.id)?.setValue(shortcutString);
     }
 
     keyUp(event: KeyboardEvent) {
         const target = event.target as HTMLInputElement;
-        const controlName = target.id === 'openShortcut' ? 'openShortcut' : 'bookmarkShortcut';
-        const currentValue = this.configForm.get(controlName)?.value;
+        // Shortcut inputs are named after their control
+        const controlName = target.id;
+        const currentValue = this.configForm.get(controlName)?.value ?? '';
 
         const keys = currentValue.split('+');
         // Check if the current value contains only modifier keys
@@ -185,12 +289,18 @@ export class ConfigFormComponent {
                 setTimeout(() => {
                     invoke('hide_window');
                 }, 4000);
-            }).catch((error) => {
-                console.log('Settings Saved', error);
+            }).catch((error: ConfigError) => {
+                // Settings without a control here (e.g. the shortcuts) are only listed in the message
+                const details = (error.fields ?? []).map((field) => {
+                    const control = this.configForm.get(field.field);
+                    control?.setErrors({ server: field.message });
+                    control?.markAsTouched();
+                    return `${field.field}: ${field.message}`;
+                });
                 this.messageService.add({
                     severity: 'error',
                     summary: 'Error',
-                    detail: `Shortcut already registered: ${error}`,
+                    detail: [error.message, ...details].join('\n'),
                     life: 10000
                 });
             });